@@ -0,0 +1,243 @@
+use crate::types::*;
+use serde::{de, Deserialize, Deserializer};
+
+fn invalid(amount: &str, reason: &str) -> SimulationError {
+    SimulationError::InvalidAmount {
+        amount: amount.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+fn parse_digits(amount: &str, digits: &str) -> Result<i128, SimulationError> {
+    if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err(invalid(amount, "not a number"));
+    }
+
+    digits
+        .parse::<i128>()
+        .map_err(|_| invalid(amount, "amount overflows"))
+}
+
+/// Parses a positive amount, either an integer in stroops or a decimal with
+/// at most `decimals` fractional digits, into stroops.
+pub(crate) fn parse_amount(amount: &str, decimals: Option<u32>) -> Result<i128, SimulationError> {
+    let text = amount.trim();
+    if text.starts_with('-') {
+        return Err(invalid(amount, "amount can't be negative"));
+    }
+
+    let stroops = match text.split_once('.') {
+        None => parse_digits(amount, text)?,
+        Some((units, fraction)) => {
+            if units.is_empty() && fraction.is_empty() {
+                return Err(invalid(amount, "not a number"));
+            }
+            let decimals = decimals.ok_or_else(|| invalid(amount, "token decimals are unknown"))?;
+            if fraction.len() > decimals as usize {
+                return Err(invalid(amount, "too many decimal places"));
+            }
+            let padding = decimals - fraction.len() as u32;
+            let units = if units.is_empty() {
+                0
+            } else {
+                parse_digits(amount, units)?
+            };
+            let fraction = if fraction.is_empty() {
+                0
+            } else {
+                parse_digits(amount, fraction)?
+            };
+
+            10i128
+                .checked_pow(padding)
+                .and_then(|scale| fraction.checked_mul(scale))
+                .and_then(|fraction| {
+                    10i128
+                        .checked_pow(decimals)?
+                        .checked_mul(units)?
+                        .checked_add(fraction)
+                })
+                .ok_or_else(|| invalid(amount, "amount overflows"))?
+        }
+    };
+
+    if stroops == 0 {
+        return Err(invalid(amount, "amount must be positive"));
+    }
+
+    Ok(stroops)
+}
+
+impl Amount {
+    /// Whether the amount is a decimal that needs the token decimals.
+    pub(crate) fn is_decimal(&self) -> bool {
+        matches!(self, Self::Text(text) if text.contains('.'))
+    }
+
+    /// The amount in stroops, scaling decimals by `decimals`.
+    pub(crate) fn to_stroops(&self, decimals: Option<u32>) -> Result<i128, SimulationError> {
+        match self {
+            Self::Units(units) if *units <= 0 => Err(invalid(
+                &units.to_string(),
+                if *units < 0 {
+                    "amount can't be negative"
+                } else {
+                    "amount must be positive"
+                },
+            )),
+            Self::Units(units) => Ok(*units),
+            Self::Text(text) => parse_amount(text, decimals),
+            Self::Inexact(value) => Err(invalid(
+                &value.to_string(),
+                "decimals and amounts above 2^64 must be sent as strings",
+            )),
+        }
+    }
+}
+
+struct AmountVisitor;
+
+impl de::Visitor<'_> for AmountVisitor {
+    type Value = Amount;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("an amount as a number or a string")
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Amount, E> {
+        Ok(Amount::Units(value as i128))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Amount, E> {
+        Ok(Amount::Units(value as i128))
+    }
+
+    fn visit_i128<E: de::Error>(self, value: i128) -> Result<Amount, E> {
+        Ok(Amount::Units(value))
+    }
+
+    // JSON numbers that don't fit in 64 bits are parsed as floats.
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Amount, E> {
+        Ok(Amount::Inexact(value))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Amount, E> {
+        Ok(Amount::Text(value.to_string()))
+    }
+}
+
+/// Reads amounts without failing on large JSON numbers, so that they are
+/// rejected with an `InvalidAmount` error instead of failing the whole body.
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(AmountVisitor)
+    }
+}
+
+/// Formats `value` stroops as a decimal with the token `decimals`, without
+/// trailing zeros (ex: 125000000 with 7 decimals is "12.5").
+pub(crate) fn format_amount(value: i128, decimals: u32) -> String {
+    let sign = if value < 0 { "-" } else { "" };
+    let value = value.unsigned_abs();
+    let Some(scale) = 10u128.checked_pow(decimals) else {
+        return format!("{}{}", sign, value);
+    };
+
+    let units = value / scale;
+    let fraction = format!("{:0width$}", value % scale, width = decimals as usize);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        format!("{}{}", sign, units)
+    } else {
+        format!("{}{}.{}", sign, units, fraction)
+    }
+}
+
+/// [`Output`] of a request with the defaults of the called function applied.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct OutputOptions {
+    pub encoding: IntEncoding,
+    pub formatted: bool,
+}
+
+impl OutputOptions {
+    pub(crate) fn resolve(output: Output, default: IntEncoding) -> Self {
+        Self {
+            encoding: output.i128.unwrap_or(default),
+            formatted: output.formatted,
+        }
+    }
+
+    pub(crate) fn int(&self, value: i128) -> JsonInt {
+        match self.encoding {
+            IntEncoding::Number => JsonInt::Number(value),
+            IntEncoding::String => JsonInt::String(value.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn amounts() {
+        assert_eq!(parse_amount("1000", None).unwrap(), 1000);
+        assert_eq!(parse_amount("12.5", Some(7)).unwrap(), 125_000_000);
+        assert_eq!(parse_amount("0.0000001", Some(7)).unwrap(), 1);
+        assert_eq!(parse_amount(".5", Some(1)).unwrap(), 5);
+        assert_eq!(
+            parse_amount("170141183460469231731687303715884105727", None).unwrap(),
+            i128::MAX
+        );
+
+        assert!(parse_amount("12.5", None).is_err());
+        assert!(parse_amount("0.00000001", Some(7)).is_err());
+        assert!(parse_amount("-1", None).is_err());
+        assert!(parse_amount("0", None).is_err());
+        assert!(parse_amount("0.0", Some(7)).is_err());
+        assert!(parse_amount("1e5", None).is_err());
+        assert!(parse_amount(".", Some(7)).is_err());
+        assert!(parse_amount("170141183460469231731687303715884105728", None).is_err());
+        assert!(parse_amount("17014118346046923173168730371588410572.7", Some(7)).is_err());
+
+        let amounts: Vec<Amount> = serde_json::from_str(
+            r#"[1000, 18446744073709551615, 170141183460469231731687303715884105727, 12.5, "12.5"]"#,
+        )
+        .unwrap();
+        assert_eq!(amounts[0].to_stroops(None).unwrap(), 1000);
+        assert_eq!(amounts[1].to_stroops(None).unwrap(), u64::MAX as i128);
+        assert!(matches!(
+            amounts[2].to_stroops(None),
+            Err(SimulationError::InvalidAmount { .. })
+        ));
+        assert!(amounts[3].to_stroops(None).is_err());
+        assert_eq!(amounts[4], Amount::Text("12.5".into()));
+
+        assert!(Amount::Text("1.5".into()).is_decimal());
+        assert_eq!(Amount::Units(10).to_stroops(None).unwrap(), 10);
+        assert!(Amount::Units(-10).to_stroops(None).is_err());
+    }
+
+    #[test]
+    fn formatting() {
+        assert_eq!(format_amount(125_000_000, 7), "12.5");
+        assert_eq!(format_amount(10_000_000, 7), "1");
+        assert_eq!(format_amount(1, 7), "0.0000001");
+        assert_eq!(format_amount(-15, 1), "-1.5");
+        assert_eq!(format_amount(i128::MIN, 0), i128::MIN.to_string());
+    }
+
+    #[test]
+    fn encoding() {
+        let strings = OutputOptions::resolve(Output::default(), IntEncoding::String);
+        assert_eq!(
+            serde_json::to_string(&strings.int(i128::MAX)).unwrap(),
+            format!("\"{}\"", i128::MAX)
+        );
+
+        let output: Output = serde_json::from_str(r#"{"i128": "number"}"#).unwrap();
+        let numbers = OutputOptions::resolve(output, IntEncoding::String);
+        assert_eq!(serde_json::to_string(&numbers.int(-5)).unwrap(), "-5");
+    }
+}
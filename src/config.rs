@@ -0,0 +1,42 @@
+// Tunables of the indexer. These are compiled into the program, so changing
+// them requires redeploying it.
+
+use crate::simulation::PaddingPolicy;
+
+/// Number of ledgers after which an oracle that hasn't published a new price
+/// is considered stale (~1 hour at 5s ledgers).
+pub(crate) const ORACLE_STALENESS_LEDGERS: u32 = 720;
+
+/// Maximum price movement between two consecutive oracle updates before the
+/// pool is flagged, in basis points (1000 = 10%).
+pub(crate) const ORACLE_MAX_DEVIATION_BPS: i128 = 1000;
+
+/// Oracles are only checked every this many ledgers (~1 minute at 5s
+/// ledgers), as each check simulates a price readout per pool.
+pub(crate) const ORACLE_CHECK_INTERVAL_LEDGERS: u32 = 12;
+
+/// Account used as source when simulating read-only calls such as the oracle
+/// price readout. It never signs anything.
+pub(crate) const READOUT_SOURCE: &str = "GAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAWHF";
+
+/// Passphrase of the network the indexer runs on, used to compute the hashes
+/// that transactions are signed over when the request doesn't set one.
+pub(crate) const NETWORK_PASSPHRASE: &str = "Public Global Stellar Network ; September 2015";
+
+/// Seconds after the current ledger close time at which simulated
+/// transactions expire when the request doesn't set a `max_time`.
+pub(crate) const DEFAULT_TX_VALIDITY_SECONDS: u64 = 300;
+
+/// Margins applied to simulated transactions when the request doesn't
+/// override them.
+pub(crate) const DEFAULT_PADDING: PaddingPolicy = PaddingPolicy {
+    instructions: 0,
+    read_bytes: 200,
+    write_bytes: 100,
+    resource_fee: 100,
+    instructions_pct: 0,
+    read_bytes_pct: 0,
+    write_bytes_pct: 0,
+    resource_fee_pct: 0,
+    inclusion_fee: 100,
+};
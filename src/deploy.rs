@@ -0,0 +1,167 @@
+use crate::{
+    config::NETWORK_PASSPHRASE,
+    encoding::decode_hex,
+    oracle,
+    simulation::{Arg, ContractCall},
+    transaction::{network_id, sha256},
+    types::*,
+    CONTRACT_ADDRESS,
+};
+use zephyr_sdk::{
+    prelude::*,
+    soroban_sdk::xdr::{
+        ContractIdPreimage, ContractIdPreimageFromAddress, Hash, HashIdPreimage,
+        HashIdPreimageContractId, ScAddress, Uint256,
+    },
+    EnvClient,
+};
+
+fn invalid(reason: String) -> SimulationError {
+    SimulationError::InvalidArgument { reason }
+}
+
+impl SimulateDeployPool {
+    /// Checks the parameters that don't need the ledger.
+    fn check(&self) -> Result<(), SimulationError> {
+        if self.periods <= 0 {
+            return Err(invalid(format!(
+                "periods must be positive, got {}",
+                self.periods
+            )));
+        }
+        if self.multiplier <= 0 {
+            return Err(invalid(format!(
+                "multiplier must be positive, got {}",
+                self.multiplier
+            )));
+        }
+        if self.volatility < 0 {
+            return Err(invalid(format!(
+                "volatility can't be negative, got {}",
+                self.volatility
+            )));
+        }
+        for symbol in [&self.symbol, &self.oracle_asset] {
+            if !oracle::is_symbol(symbol) {
+                return Err(invalid(format!("invalid symbol {}", symbol)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the token exists and that the oracle publishes a price
+    /// for the oracle asset. The parameters are expected to have passed
+    /// [`Self::check`] already, as [`ContractCall::deploy`] does.
+    pub(crate) fn validate(&self, env: &EnvClient) -> Result<(), SimulationError> {
+        let token = stellar_strkey::Contract::from_string(&self.token_id).map_err(|_| {
+            SimulationError::InvalidAddress {
+                address: self.token_id.clone(),
+            }
+        })?;
+        if !matches!(env.read_contract_instance(token.0), Ok(Some(_))) {
+            return Err(invalid(format!("unknown token {}", self.token_id)));
+        }
+        if oracle::read_last_price(env, &self.oracle, &self.oracle_asset).is_none() {
+            return Err(invalid(format!(
+                "oracle {} has no price for {}",
+                self.oracle, self.oracle_asset
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn salt(&self) -> Result<[u8; 32], SimulationError> {
+        decode_hex(&self.salt)
+            .ok()
+            .and_then(|salt| salt.try_into().ok())
+            .ok_or_else(|| invalid(format!("invalid salt {}", self.salt)))
+    }
+}
+
+impl ContractCall {
+    /// Builds the factory call deploying a pool with the given parameters.
+    pub(crate) fn deploy(request: &SimulateDeployPool) -> Result<Self, SimulationError> {
+        request.check()?;
+        let salt = request.salt()?;
+
+        let args = [
+            Arg::Hash(request.salt.clone()),
+            Arg::Address(request.token_id.clone()),
+            Arg::I32(request.periods),
+            Arg::Address(request.oracle.clone()),
+            Arg::Symbol(request.symbol.clone()),
+            Arg::Bool(request.external),
+            Arg::Symbol(request.oracle_asset.clone()),
+            Arg::I128(request.volatility),
+            Arg::Address(request.admin.clone()),
+            Arg::I32(request.multiplier),
+        ];
+
+        Ok(Self {
+            contract: CONTRACT_ADDRESS,
+            from: request.from.clone(),
+            function: "deploy".to_string(),
+            args: args.iter().map(Arg::to_scval).collect::<Result<_, _>>()?,
+            admin: false,
+            deploy_salt: Some(salt),
+        })
+    }
+}
+
+/// Address of the pool the factory deploys with `salt`. The factory only
+/// lives on the network the indexer runs on, whatever passphrase the request
+/// signs with.
+pub(crate) fn predicted_address(env: &EnvClient, salt: [u8; 32]) -> String {
+    let preimage = HashIdPreimage::ContractId(HashIdPreimageContractId {
+        network_id: network_id(env, NETWORK_PASSPHRASE),
+        contract_id_preimage: ContractIdPreimage::Address(ContractIdPreimageFromAddress {
+            address: ScAddress::Contract(Hash(CONTRACT_ADDRESS)),
+            salt: Uint256(salt),
+        }),
+    });
+    let contract = sha256(env, &preimage.to_xdr(Limits::none()).unwrap());
+
+    stellar_strkey::Contract(contract).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deploy_call() {
+        let mut request = SimulateDeployPool {
+            from: "GAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAWHF".to_string(),
+            salt: "01".repeat(32),
+            token_id: "CAYRXB3BP4VPUTH6NFAI543HOVOD6AVN4KKASSIR3GALG23EV5DQT5G5".to_string(),
+            periods: 10,
+            oracle: "CAYRXB3BP4VPUTH6NFAI543HOVOD6AVN4KKASSIR3GALG23EV5DQT5G5".to_string(),
+            symbol: "XLM".to_string(),
+            external: false,
+            oracle_asset: "XLM".to_string(),
+            volatility: 100,
+            admin: "GAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAWHF".to_string(),
+            multiplier: 2,
+        };
+        let call = ContractCall::deploy(&request).unwrap();
+        assert_eq!(call.contract, CONTRACT_ADDRESS);
+        assert_eq!(call.deploy_salt, Some([1; 32]));
+        assert_eq!(call.args.len(), 10);
+
+        request.periods = 0;
+        assert!(ContractCall::deploy(&request).is_err());
+        request.periods = 10;
+        request.oracle_asset = "BTC/USD".to_string();
+        assert!(ContractCall::deploy(&request).is_err());
+        request.oracle_asset = "XLM".to_string();
+        request.salt = "01".to_string();
+        assert!(ContractCall::deploy(&request).is_err());
+
+        let env = EnvClient::empty();
+        let address = predicted_address(&env, [1; 32]);
+        assert!(stellar_strkey::Contract::from_string(&address).is_ok());
+        assert_ne!(address, predicted_address(&env, [2; 32]));
+    }
+}
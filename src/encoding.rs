@@ -0,0 +1,89 @@
+use zephyr_sdk::{
+    soroban_sdk::{xdr::ScVal, Env, IntoVal, TryFromVal, Val},
+    EnvClient, SdkError,
+};
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub(crate) fn decode_hex(hex: &str) -> Result<Vec<u8>, String> {
+    let hex = hex.strip_prefix("0x").unwrap_or(hex);
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return Err(format!("invalid hex bytes {}", hex));
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|idx| {
+            u8::from_str_radix(&hex[idx..idx + 2], 16)
+                .map_err(|_| format!("invalid hex bytes {}", hex))
+        })
+        .collect()
+}
+
+/// Percent-encodes `value` for a URI query, keeping only the unreserved
+/// characters of RFC 3986.
+pub(crate) fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Converts `val` into `T`. Zephyr programs convert through the host, while
+/// native builds such as the tests use the Soroban environment directly.
+pub(crate) fn from_scval<T>(env: &EnvClient, val: &ScVal) -> Result<T, SdkError>
+where
+    T: TryFromVal<Env, Val>,
+{
+    #[cfg(target_family = "wasm")]
+    return env.try_from_scval(val);
+
+    #[cfg(not(target_family = "wasm"))]
+    {
+        let val = Val::try_from_val(env.soroban(), val).map_err(|_| SdkError::Conversion)?;
+        T::try_from_val(env.soroban(), &val).map_err(|_| SdkError::Conversion)
+    }
+}
+
+/// Converts `val` into an `ScVal`, like [`from_scval`].
+pub(crate) fn to_scval<T>(env: &EnvClient, val: T) -> ScVal
+where
+    T: IntoVal<Env, Val>,
+{
+    #[cfg(target_family = "wasm")]
+    return env.to_scval(val);
+
+    #[cfg(not(target_family = "wasm"))]
+    ScVal::try_from_val(env.soroban(), &val.into_val(env.soroban())).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex() {
+        assert_eq!(to_hex(&[0, 171, 16]), "00ab10");
+        assert_eq!(decode_hex("0x00ab10").unwrap(), vec![0, 171, 16]);
+        assert!(decode_hex("0ab").is_err());
+        assert!(decode_hex("zz").is_err());
+    }
+
+    #[test]
+    fn percent() {
+        assert_eq!(percent_encode("AAAA+/8="), "AAAA%2B%2F8%3D");
+        assert_eq!(
+            percent_encode("Public Global Stellar Network ; September 2015"),
+            "Public%20Global%20Stellar%20Network%20%3B%20September%202015"
+        );
+        assert_eq!(percent_encode("a-b_c.d~"), "a-b_c.d~");
+        assert_eq!(percent_encode("é"), "%C3%A9");
+    }
+}
@@ -1,4 +1,4 @@
-use fixed_point_math::{FixedPoint, STROOP};
+use fixed_point_math::FixedPoint;
 use zephyr_sdk::soroban_sdk::Env;
 
 pub(crate) fn calculate_period(current: i128, genesis: i128, periods: i128) -> i32 {
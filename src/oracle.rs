@@ -0,0 +1,198 @@
+use crate::{
+    config::{
+        ORACLE_CHECK_INTERVAL_LEDGERS, ORACLE_MAX_DEVIATION_BPS, ORACLE_STALENESS_LEDGERS,
+        READOUT_SOURCE,
+    },
+    types::*,
+};
+use zephyr_sdk::{
+    prelude::*,
+    soroban_sdk::{vec, IntoVal, Symbol},
+    EnvClient,
+};
+
+pub(crate) const STALE_ORACLE: &str = "stale_oracle";
+pub(crate) const PRICE_JUMP: &str = "oracle_price_jump";
+
+/// Whether `value` is a valid symbol: up to 32 characters among
+/// `[A-Za-z0-9_]`.
+pub(crate) fn is_symbol(value: &str) -> bool {
+    value.len() <= 32
+        && value
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'_')
+}
+
+/// Converts the oracle asset of a pool into a symbol, or `None` when it
+/// isn't one.
+pub(crate) fn asset_symbol(env: &EnvClient, asset: &str) -> Option<Symbol> {
+    // invalid symbols make the host trap rather than return an error.
+    is_symbol(asset).then(|| Symbol::new(env.soroban(), asset))
+}
+
+/// Reads the latest price published by a SEP-40 oracle for `asset`. Returns
+/// `None` as well when `asset` isn't a valid symbol.
+pub(crate) fn read_last_price(env: &EnvClient, oracle: &str, asset: &str) -> Option<PriceData> {
+    let oracle = stellar_strkey::Contract::from_string(oracle).ok()?.0;
+    let asset = Asset::Other(asset_symbol(env, asset)?);
+
+    let simulation = env
+        .simulate_contract_call(
+            READOUT_SOURCE.into(),
+            oracle,
+            Symbol::new(env.soroban(), "lastprice"),
+            vec![env.soroban(), asset.into_val(env.soroban())],
+        )
+        .ok()?;
+
+    env.try_from_scval::<Option<PriceData>>(&simulation.invoke_result.ok()?)
+        .ok()?
+}
+
+/// Price movement between two consecutive updates, in basis points.
+pub(crate) fn deviation_bps(previous: i128, current: i128) -> i128 {
+    if previous <= 0 {
+        return 0;
+    }
+
+    (current - previous).abs().saturating_mul(10_000) / previous
+}
+
+pub(crate) fn is_stale(last_update_ledger: u32, current_ledger: u32) -> bool {
+    current_ledger.saturating_sub(last_update_ledger) > ORACLE_STALENESS_LEDGERS
+}
+
+pub(crate) fn is_price_jump(deviation: i128) -> bool {
+    deviation > ORACLE_MAX_DEVIATION_BPS
+}
+
+/// Whether the oracles are checked in this ledger.
+pub(crate) fn is_check_ledger(ledger: u32) -> bool {
+    ledger.is_multiple_of(ORACLE_CHECK_INTERVAL_LEDGERS)
+}
+
+fn alert(env: &EnvClient, pool: &str, kind: &str, ledger: u32, detail: String) {
+    env.log().debug(
+        format!("{} alert for pool {}: {}", kind, pool, detail),
+        None,
+    );
+    AlertsTable {
+        pool: pool.to_string(),
+        kind: kind.to_string(),
+        ledger,
+        detail,
+    }
+    .put(env);
+}
+
+/// Checks the oracle of a pool, taken from its cached state, for a fresh
+/// price, records it and raises alerts on staleness or abnormal price
+/// movements.
+pub(crate) fn monitor(env: &EnvClient, state: &PoolStateTable, ledger: u32) {
+    let pool = state.address.as_str();
+    let price = read_last_price(env, &state.oracle, &state.asset);
+
+    let previous = env
+        .read_filter()
+        .column_equal_to("pool", pool.to_string())
+        .read::<OraclesTable>()
+        .unwrap_or_default()
+        .pop();
+
+    let Some(previous) = previous else {
+        let row = OraclesTable {
+            pool: pool.to_string(),
+            price: price.as_ref().map_or(0, |price| price.price),
+            timestamp: price.as_ref().map_or(0, |price| price.timestamp),
+            ledger,
+            deviation: 0,
+            alerted: false,
+        };
+        row.put(env);
+        return;
+    };
+
+    let row = match price {
+        Some(price) if price.timestamp != previous.timestamp => {
+            let deviation = deviation_bps(previous.price, price.price);
+            if is_price_jump(deviation) {
+                alert(
+                    env,
+                    pool,
+                    PRICE_JUMP,
+                    ledger,
+                    format!(
+                        "price moved {} bps ({} -> {})",
+                        deviation, previous.price, price.price
+                    ),
+                );
+            }
+
+            OraclesTable {
+                pool: pool.to_string(),
+                price: price.price,
+                timestamp: price.timestamp,
+                ledger,
+                deviation,
+                alerted: false,
+            }
+        }
+        _ if !previous.alerted && is_stale(previous.ledger, ledger) => {
+            alert(
+                env,
+                pool,
+                STALE_ORACLE,
+                ledger,
+                format!("no fresh price since ledger {}", previous.ledger),
+            );
+
+            OraclesTable {
+                alerted: true,
+                ..previous
+            }
+        }
+        _ => return,
+    };
+
+    env.update()
+        .column_equal_to("pool", pool.to_string())
+        .execute(&row)
+        .unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deviation() {
+        assert_eq!(deviation_bps(0, 100), 0);
+        assert_eq!(deviation_bps(100, 100), 0);
+        assert_eq!(deviation_bps(100, 111), 1100);
+        assert_eq!(deviation_bps(100, 89), 1100);
+        assert!(is_price_jump(deviation_bps(100, 111)));
+        assert!(!is_price_jump(deviation_bps(100, 110)));
+    }
+
+    #[test]
+    fn staleness() {
+        assert!(!is_stale(100, 100 + ORACLE_STALENESS_LEDGERS));
+        assert!(is_stale(100, 101 + ORACLE_STALENESS_LEDGERS));
+        assert!(!is_stale(200, 100));
+    }
+
+    #[test]
+    fn asset_symbols() {
+        let env = EnvClient::empty();
+        assert!(asset_symbol(&env, "XLM").is_some());
+        assert!(asset_symbol(&env, "LONG_ASSET_NAME_42").is_some());
+        assert!(asset_symbol(&env, "BTC/USD").is_none());
+        assert!(asset_symbol(&env, &"X".repeat(33)).is_none());
+    }
+
+    #[test]
+    fn check_interval() {
+        assert!(is_check_ledger(ORACLE_CHECK_INTERVAL_LEDGERS * 3));
+        assert!(!is_check_ledger(ORACLE_CHECK_INTERVAL_LEDGERS * 3 + 1));
+    }
+}
@@ -0,0 +1,456 @@
+use crate::{
+    amount::{format_amount, OutputOptions},
+    encoding::from_scval,
+    maths::actual_period,
+    preflight::period_error,
+    types::*,
+};
+use zephyr_sdk::{
+    prelude::*,
+    soroban_sdk::{
+        xdr::{LedgerEntry, LedgerEntryData, LedgerKey, ScAddress, ScMapEntry, ScVal},
+        Address, Env, TryFromVal, Val,
+    },
+    EnvClient, SdkError,
+};
+
+/// Pool parameters stored in the contract's instance storage.
+#[derive(Clone)]
+pub(crate) struct PoolConfig {
+    pub contract: [u8; 32],
+    pub token_id: String,
+    pub genesis_period: i32,
+    pub periods: i32,
+    pub oracle: String,
+    pub symbol: String,
+    pub external: bool,
+    pub oracle_asset: String,
+    pub volatility: i128,
+    pub admin: String,
+    pub multiplier: i32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum PoolConfigError {
+    InvalidAddress,
+    InstanceNotFound,
+    NotAnInstance,
+    MissingKey(InstanceDataKey),
+    InvalidValue(InstanceDataKey),
+    EntryRead(SdkError),
+}
+
+impl std::fmt::Display for PoolConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidAddress => write!(f, "invalid pool address"),
+            Self::InstanceNotFound => write!(f, "pool instance not found in the ledger"),
+            Self::NotAnInstance => write!(f, "ledger entry is not a contract instance"),
+            Self::MissingKey(key) => write!(f, "missing instance key {:?}", key),
+            Self::InvalidValue(key) => {
+                write!(f, "unexpected value type for instance key {:?}", key)
+            }
+            Self::EntryRead(error) => write!(f, "cannot read pool entry: {}", error),
+        }
+    }
+}
+
+impl PoolConfig {
+    /// Reads and decodes the instance storage of the pool at `address`.
+    pub(crate) fn read(env: &EnvClient, address: &str) -> Result<Self, PoolConfigError> {
+        let contract = stellar_strkey::Contract::from_string(address)
+            .map_err(|_| PoolConfigError::InvalidAddress)?
+            .0;
+        let instance = env
+            .read_contract_instance(contract)
+            .ok()
+            .flatten()
+            .ok_or(PoolConfigError::InstanceNotFound)?;
+        let LedgerEntryData::ContractData(data) = instance.entry.data else {
+            return Err(PoolConfigError::NotAnInstance);
+        };
+        let ScVal::ContractInstance(instance) = data.val else {
+            return Err(PoolConfigError::NotAnInstance);
+        };
+
+        Self::decode(
+            env,
+            contract,
+            instance
+                .storage
+                .as_ref()
+                .map_or(&[], |map| map.0.as_slice()),
+        )
+    }
+
+    /// Decodes the instance storage map in a single pass.
+    pub(crate) fn decode(
+        env: &EnvClient,
+        contract: [u8; 32],
+        storage: &[ScMapEntry],
+    ) -> Result<Self, PoolConfigError> {
+        fn value<T>(
+            env: &EnvClient,
+            key: InstanceDataKey,
+            val: &ScVal,
+        ) -> Result<Option<T>, PoolConfigError>
+        where
+            T: TryFromVal<Env, Val>,
+        {
+            from_scval(env, val)
+                .map(Some)
+                .map_err(|_| PoolConfigError::InvalidValue(key))
+        }
+
+        let mut token_id = None;
+        let mut genesis_period = None;
+        let mut periods = None;
+        let mut oracle = None;
+        let mut symbol = None;
+        let mut external = None;
+        let mut oracle_asset = None;
+        let mut volatility = None;
+        let mut admin = None;
+        let mut multiplier = None;
+
+        for entry in storage {
+            let Ok(key) = from_scval::<InstanceDataKey>(env, &entry.key) else {
+                continue;
+            };
+            let val = &entry.val;
+
+            match key {
+                InstanceDataKey::TokenId => token_id = value(env, key, val)?,
+                InstanceDataKey::GenesisPeriod => genesis_period = value(env, key, val)?,
+                InstanceDataKey::Periods => periods = value(env, key, val)?,
+                InstanceDataKey::Oracle => oracle = value(env, key, val)?,
+                InstanceDataKey::Symbol => symbol = value(env, key, val)?,
+                InstanceDataKey::External => external = value(env, key, val)?,
+                InstanceDataKey::OracleAsset => oracle_asset = value(env, key, val)?,
+                InstanceDataKey::Volatility => volatility = value(env, key, val)?,
+                InstanceDataKey::Admin => admin = value(env, key, val)?,
+                InstanceDataKey::Multiplier => multiplier = value(env, key, val)?,
+            }
+        }
+
+        use InstanceDataKey::*;
+        let missing = PoolConfigError::MissingKey;
+
+        Ok(Self {
+            contract,
+            token_id: token_id.ok_or(missing(TokenId))?,
+            genesis_period: genesis_period.ok_or(missing(GenesisPeriod))?,
+            periods: periods.ok_or(missing(Periods))?,
+            oracle: oracle.ok_or(missing(Oracle))?,
+            symbol: symbol.ok_or(missing(Symbol))?,
+            external: external.ok_or(missing(External))?,
+            oracle_asset: oracle_asset.ok_or(missing(OracleAsset))?,
+            volatility: volatility.ok_or(missing(Volatility))?,
+            admin: admin.ok_or(missing(Admin))?,
+            multiplier: multiplier.ok_or(missing(Multiplier))?,
+        })
+    }
+
+    /// Reads a single persistent entry of the pool by building its ledger key,
+    /// rather than scanning all of the contract's entries. `None` means the
+    /// entry doesn't exist, while host failures are returned as errors.
+    pub(crate) fn read_entry<T>(
+        &self,
+        env: &EnvClient,
+        key: PersistentDataKey,
+    ) -> Result<Option<T>, PoolConfigError>
+    where
+        T: TryFromVal<Env, Val> + std::fmt::Debug,
+    {
+        env.read_contract_entry_by_key(self.contract, key)
+            .map_err(PoolConfigError::EntryRead)
+    }
+
+    /// Current period of the pool (ex: 1).
+    pub(crate) fn current_period(&self, env: &EnvClient) -> i32 {
+        actual_period(env.soroban(), self.genesis_period, self.periods)
+    }
+
+    /// Balance of `user` in `period`, `None` when they never had a position.
+    pub(crate) fn balance(
+        &self,
+        env: &EnvClient,
+        user: &Address,
+        period: i32,
+    ) -> Result<Option<i128>, PoolConfigError> {
+        self.read_entry(
+            env,
+            PersistentDataKey::Balance(BalanceObject {
+                address: user.clone(),
+                period,
+            }),
+        )
+    }
+
+    /// Matured fees of `user` in `period` that weren't withdrawn yet.
+    pub(crate) fn matured_fees(
+        &self,
+        env: &EnvClient,
+        user: &Address,
+        period: i32,
+    ) -> Result<Option<i128>, PoolConfigError> {
+        self.read_entry(
+            env,
+            PersistentDataKey::MaturedFeesParticular(BalanceObject {
+                address: user.clone(),
+                period,
+            }),
+        )
+    }
+
+    /// Periods that `user` can withdraw, by the same rule as a single
+    /// withdrawal request: matured, with a balance or matured fees left.
+    pub(crate) fn withdrawable_periods(
+        &self,
+        env: &EnvClient,
+        user: &Address,
+    ) -> Result<Vec<i32>, PoolConfigError> {
+        let current_period = self.current_period(env);
+        let mut periods = Vec::new();
+        for period in 1..current_period {
+            let balance = self.balance(env, user, period)?;
+            let fees = self.matured_fees(env, user, period)?;
+            if period_error(period, current_period, balance, fees).is_none() {
+                periods.push(period);
+            }
+        }
+
+        Ok(periods)
+    }
+}
+
+/// Reads a pool's parameters and current period totals from the ledger.
+/// Totals that were never written default to 0, while failing reads abort so
+/// that the cached state isn't overwritten with zeros.
+pub(crate) fn read_pool_state(
+    env: &EnvClient,
+    address: &str,
+) -> Result<PoolStateTable, PoolConfigError> {
+    let config = PoolConfig::read(env, address)?;
+
+    let period = config.current_period(env);
+
+    let tot_liquidity: i128 = config
+        .read_entry(env, PersistentDataKey::TotLiquidity(period))?
+        .unwrap_or(0);
+    let tot_supply: i128 = config
+        .read_entry(env, PersistentDataKey::TotSupply(period))?
+        .unwrap_or(0);
+    let refund_global: i128 = config
+        .read_entry(env, PersistentDataKey::RefundGlobal(period))?
+        .unwrap_or(0);
+    env.log().debug(
+        format!(
+            "period {:?}: total liquidity {:?}, total supply {:?}, total refund {:?}",
+            period, tot_liquidity as i64, tot_supply as i64, refund_global as i64
+        ),
+        None,
+    );
+
+    Ok(PoolStateTable {
+        address: address.to_string(),
+        token_id: config.token_id,
+        genesis: config.genesis_period,
+        periods: config.periods,
+        oracle: config.oracle,
+        symbol: config.symbol,
+        external: config.external,
+        asset: config.oracle_asset,
+        vol: config.volatility,
+        admin: config.admin,
+        mult: config.multiplier,
+        liquidity: tot_liquidity,
+        supply: tot_supply,
+        refund: refund_global,
+        period,
+    })
+}
+
+/// Recomputes the cached state of a pool and stores it.
+pub(crate) fn refresh(env: &EnvClient, address: &str) {
+    let state = match read_pool_state(env, address) {
+        Ok(state) => state,
+        Err(error) => {
+            env.log()
+                .debug(format!("cannot read pool {}: {}", address, error), None);
+            return;
+        }
+    };
+    env.log()
+        .debug(format!("refreshing state of pool {}", address), None);
+
+    let existing = env
+        .read_filter()
+        .column_equal_to("address", address.to_string())
+        .read::<PoolStateTable>()
+        .unwrap_or_default();
+
+    if existing.is_empty() {
+        state.put(env);
+    } else {
+        env.update()
+            .column_equal_to("address", address.to_string())
+            .execute(&state)
+            .unwrap();
+    }
+}
+
+fn entry_contract(entry: &LedgerEntry) -> Option<[u8; 32]> {
+    let LedgerEntryData::ContractData(data) = &entry.data else {
+        return None;
+    };
+    let ScAddress::Contract(hash) = &data.contract else {
+        return None;
+    };
+
+    Some(hash.0)
+}
+
+/// Returns the pools among `pools` that need their cached state refreshed in
+/// this ledger: their entries changed, or their current period rolled over.
+pub(crate) fn stale_pools(env: &EnvClient, pools: &[PoolsTable]) -> Vec<String> {
+    let changes = env.reader().v1_success_ledger_entries();
+    let mut touched: Vec<[u8; 32]> = changes
+        .created
+        .iter()
+        .chain(changes.updated.iter())
+        .filter_map(entry_contract)
+        .collect();
+    touched.extend(changes.removed.iter().filter_map(|key| match key {
+        LedgerKey::ContractData(key) => match &key.contract {
+            ScAddress::Contract(hash) => Some(hash.0),
+            _ => None,
+        },
+        _ => None,
+    }));
+
+    let cached = env.read::<PoolStateTable>();
+
+    pools
+        .iter()
+        .filter(|pool| {
+            let Ok(contract) = stellar_strkey::Contract::from_string(&pool.address) else {
+                return false;
+            };
+            let Some(state) = cached.iter().find(|state| state.address == pool.address) else {
+                return true;
+            };
+
+            touched.contains(&contract.0)
+                || actual_period(env.soroban(), state.genesis, state.periods) != state.period
+        })
+        .map(|pool| pool.address.clone())
+        .collect()
+}
+
+impl PoolStateTable {
+    /// Renders the cached state, formatting token amounts with `decimals`
+    /// when the caller asked for them.
+    pub(crate) fn to_pool_data(
+        &self,
+        oracle_stale: bool,
+        oracle_price_jump: bool,
+        output: &OutputOptions,
+        decimals: Option<u32>,
+    ) -> PoolData {
+        let formatted = decimals
+            .filter(|_| output.formatted)
+            .map(|decimals| FormattedPoolData {
+                tot_liquidity: format_amount(self.liquidity, decimals),
+                tot_supply: format_amount(self.supply, decimals),
+                refund_global: format_amount(self.refund, decimals),
+            });
+
+        PoolData {
+            address: self.address.clone(),
+            token_id: self.token_id.clone(),
+            genesis_period: self.genesis,
+            periods: self.periods,
+            oracle: self.oracle.clone(),
+            symbol: self.symbol.clone(),
+            external: self.external,
+            oracle_asset: self.asset.clone(),
+            volatility: output.int(self.vol),
+            admin: self.admin.clone(),
+            multiplier: self.mult,
+            tot_liquidity: output.int(self.liquidity),
+            tot_supply: output.int(self.supply),
+            refund_global: output.int(self.refund),
+            oracle_stale,
+            oracle_price_jump,
+            formatted,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::to_scval;
+    use zephyr_sdk::soroban_sdk::{IntoVal, Symbol};
+
+    const ADDRESS: &str = "CAYRXB3BP4VPUTH6NFAI543HOVOD6AVN4KKASSIR3GALG23EV5DQT5G5";
+
+    fn entry(
+        env: &EnvClient,
+        key: impl IntoVal<Env, Val>,
+        val: impl IntoVal<Env, Val>,
+    ) -> ScMapEntry {
+        ScMapEntry {
+            key: to_scval(env, key),
+            val: to_scval(env, val),
+        }
+    }
+
+    fn storage(env: &EnvClient) -> Vec<ScMapEntry> {
+        let string = |s: &str| zephyr_sdk::soroban_sdk::String::from_str(env.soroban(), s);
+
+        vec![
+            entry(env, InstanceDataKey::TokenId, string(ADDRESS)),
+            entry(env, InstanceDataKey::GenesisPeriod, 1i32),
+            entry(env, InstanceDataKey::Periods, 10i32),
+            entry(env, InstanceDataKey::Oracle, string(ADDRESS)),
+            entry(env, InstanceDataKey::Symbol, string("XLM")),
+            entry(env, InstanceDataKey::External, false),
+            entry(env, InstanceDataKey::OracleAsset, string("XLM")),
+            entry(env, InstanceDataKey::Volatility, 100i128),
+            entry(env, InstanceDataKey::Admin, string(ADDRESS)),
+            entry(env, InstanceDataKey::Multiplier, 2i32),
+        ]
+    }
+
+    #[test]
+    fn decode_storage() {
+        let env = EnvClient::empty();
+
+        let mut entries = storage(&env);
+        entries.push(entry(&env, Symbol::new(env.soroban(), "Unrelated"), 7u32));
+        let config = PoolConfig::decode(&env, [0; 32], &entries).unwrap();
+        assert_eq!(config.periods, 10);
+        assert_eq!(config.admin, ADDRESS);
+        assert_eq!(config.oracle_asset, "XLM");
+
+        let mut entries = storage(&env);
+        entries.retain(|entry| entry.key != to_scval(&env, InstanceDataKey::Admin));
+        assert!(matches!(
+            PoolConfig::decode(&env, [0; 32], &entries),
+            Err(PoolConfigError::MissingKey(InstanceDataKey::Admin))
+        ));
+
+        let mut entries = storage(&env);
+        entries[2] = entry(
+            &env,
+            InstanceDataKey::Periods,
+            Symbol::new(env.soroban(), "ten"),
+        );
+        assert!(matches!(
+            PoolConfig::decode(&env, [0; 32], &entries),
+            Err(PoolConfigError::InvalidValue(InstanceDataKey::Periods))
+        ));
+    }
+}
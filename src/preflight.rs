@@ -0,0 +1,323 @@
+use crate::{
+    config::READOUT_SOURCE,
+    pool::{PoolConfig, PoolConfigError},
+    simulation::{address_to_scval, read_sequence, SimulationOptions, TxSource},
+    transaction,
+    types::*,
+};
+use zephyr_sdk::{
+    prelude::*,
+    soroban_sdk::{
+        vec,
+        xdr::{
+            AccountId, AlphaNum12, AlphaNum4, AssetCode12, AssetCode4, ChangeTrustAsset,
+            ChangeTrustOp, Operation, OperationBody, PublicKey, ScError, ScVal, SequenceNumber,
+            Transaction, TransactionEnvelope, TransactionExt, TransactionV1Envelope, Uint256,
+        },
+        Address, IntoVal, Symbol,
+    },
+    EnvClient,
+};
+
+/// Error code returned by the Stellar Asset Contract when the account has no
+/// trustline to the asset.
+const SAC_TRUSTLINE_MISSING: u32 = 13;
+
+/// Parses the `CODE:ISSUER` name of a Stellar Asset Contract into the asset
+/// of a trustline. Returns `None` for the native asset and malformed names.
+pub(crate) fn trustline_asset(name: &str) -> Option<ChangeTrustAsset> {
+    let (code, issuer) = name.split_once(':')?;
+    let issuer = stellar_strkey::ed25519::PublicKey::from_string(issuer).ok()?;
+    let issuer = AccountId(PublicKey::PublicKeyTypeEd25519(Uint256(issuer.0)));
+    if code.is_empty() || !code.bytes().all(|byte| byte.is_ascii_alphanumeric()) {
+        return None;
+    }
+
+    let asset = if code.len() <= 4 {
+        let mut asset_code = [0; 4];
+        asset_code[..code.len()].copy_from_slice(code.as_bytes());
+        ChangeTrustAsset::CreditAlphanum4(AlphaNum4 {
+            asset_code: AssetCode4(asset_code),
+            issuer,
+        })
+    } else if code.len() <= 12 {
+        let mut asset_code = [0; 12];
+        asset_code[..code.len()].copy_from_slice(code.as_bytes());
+        ChangeTrustAsset::CreditAlphanum12(AlphaNum12 {
+            asset_code: AssetCode12(asset_code),
+            issuer,
+        })
+    } else {
+        return None;
+    };
+
+    Some(asset)
+}
+
+fn read_token_name(env: &EnvClient, token: [u8; 32]) -> Option<String> {
+    let simulation = env
+        .simulate_contract_call(
+            READOUT_SOURCE.into(),
+            token,
+            Symbol::new(env.soroban(), "name"),
+            vec![env.soroban()],
+        )
+        .ok()?;
+    env.try_from_scval(&simulation.invoke_result.ok()?).ok()
+}
+
+pub(crate) fn read_pool(env: &EnvClient, contract: &str) -> Result<PoolConfig, SimulationError> {
+    PoolConfig::read(env, contract).map_err(|error| SimulationError::InvalidArgument {
+        reason: format!("cannot read pool {}: {}", contract, error),
+    })
+}
+
+fn entry_error(error: PoolConfigError) -> SimulationError {
+    SimulationError::HostError {
+        reason: error.to_string(),
+    }
+}
+
+pub(crate) fn token_address(config: &PoolConfig) -> Result<[u8; 32], SimulationError> {
+    stellar_strkey::Contract::from_string(&config.token_id)
+        .map(|token| token.0)
+        .map_err(|_| SimulationError::InvalidAddress {
+            address: config.token_id.clone(),
+        })
+}
+
+/// Reads the decimals of the pool's token.
+pub(crate) fn token_decimals(env: &EnvClient, contract: &str) -> Result<u32, SimulationError> {
+    let config = read_pool(env, contract)?;
+
+    read_token_decimals(env, &config.token_id).ok_or_else(|| SimulationError::InvalidArgument {
+        reason: format!("cannot read the decimals of token {}", config.token_id),
+    })
+}
+
+/// Reads the decimals of the SEP-41 token at `token`.
+pub(crate) fn read_token_decimals(env: &EnvClient, token: &str) -> Option<u32> {
+    let token = stellar_strkey::Contract::from_string(token).ok()?.0;
+    let simulation = env
+        .simulate_contract_call(
+            READOUT_SOURCE.into(),
+            token,
+            Symbol::new(env.soroban(), "decimals"),
+            vec![env.soroban()],
+        )
+        .ok()?;
+
+    env.try_from_scval(&simulation.invoke_result.ok()?).ok()
+}
+
+/// Builds the transaction adding the trustline `from` is missing.
+fn change_trust_tx(
+    env: &EnvClient,
+    from: &str,
+    asset: ChangeTrustAsset,
+    options: &SimulationOptions,
+) -> Option<String> {
+    let source = TxSource::account(from).ok()?;
+    let sequence = read_sequence(env, source.account).ok()?;
+
+    let tx = Transaction {
+        source_account: source.muxed,
+        fee: options.padding.inclusion_fee,
+        seq_num: SequenceNumber(sequence + 1),
+        cond: transaction::preconditions(
+            options.bounds.as_ref(),
+            env.soroban().ledger().timestamp(),
+        )
+        .ok()?,
+        memo: transaction::memo(options.memo.as_ref()).ok()?,
+        operations: std::vec![Operation {
+            source_account: None,
+            body: OperationBody::ChangeTrust(ChangeTrustOp {
+                line: asset,
+                limit: i64::MAX,
+            }),
+        }]
+        .try_into()
+        .unwrap(),
+        ext: TransactionExt::V0,
+    };
+    let envelope = TransactionEnvelope::Tx(TransactionV1Envelope {
+        tx,
+        signatures: std::vec![].try_into().unwrap(),
+    });
+
+    Some(envelope.to_xdr_base64(Limits::none()).unwrap())
+}
+
+/// Checks that `from` holds at least `amount` of the pool's token before a
+/// deposit or subscription, so that the caller gets a clear error rather
+/// than an opaque simulation failure.
+pub(crate) fn check_balance(
+    env: &EnvClient,
+    contract: &str,
+    from: &str,
+    amount: i128,
+    options: &SimulationOptions,
+) -> Result<(), SimulationError> {
+    let config = read_pool(env, contract)?;
+    let token = token_address(&config)?;
+    let user = address_to_scval(from).map_err(|_| SimulationError::InvalidAddress {
+        address: from.to_string(),
+    })?;
+    let user: Address = env.from_scval(&user);
+
+    let simulation = env
+        .simulate_contract_call(
+            READOUT_SOURCE.into(),
+            token,
+            Symbol::new(env.soroban(), "balance"),
+            vec![env.soroban(), user.into_val(env.soroban())],
+        )
+        .map_err(|error| SimulationError::HostError {
+            reason: error.to_string(),
+        })?;
+
+    let balance = match simulation.invoke_result {
+        Ok(balance) => match env.try_from_scval::<i128>(&balance) {
+            Ok(balance) => balance,
+            // not a SEP-41 balance, nothing to check against.
+            Err(_) => return Ok(()),
+        },
+        Err(ScVal::Error(ScError::Contract(SAC_TRUSTLINE_MISSING))) => {
+            let asset = read_token_name(env, token);
+            let change_trust = asset
+                .as_deref()
+                .and_then(trustline_asset)
+                .and_then(|asset| change_trust_tx(env, from, asset, options));
+
+            return Err(SimulationError::MissingTrustline {
+                account: from.to_string(),
+                asset: asset.unwrap_or(config.token_id),
+                change_trust,
+            });
+        }
+        // leave other failures to the simulation of the call itself.
+        Err(_) => return Ok(()),
+    };
+
+    if balance < amount {
+        return Err(SimulationError::InsufficientBalance {
+            account: from.to_string(),
+            token: config.token_id,
+            balance: options.output.int(balance),
+            required: options.output.int(amount),
+        });
+    }
+
+    Ok(())
+}
+
+/// Checks why a position in `period` can't be withdrawn, if it can't. A
+/// matured period stays withdrawable while either its balance or its matured
+/// fees are left.
+pub(crate) fn period_error(
+    period: i32,
+    current_period: i32,
+    balance: Option<i128>,
+    matured_fees: Option<i128>,
+) -> Option<PeriodError> {
+    if period >= current_period {
+        return Some(PeriodError::NotMatured);
+    }
+
+    match (balance, matured_fees) {
+        (None, None) => Some(PeriodError::NoBalance),
+        (balance, fees) if balance.unwrap_or(0) > 0 || fees.unwrap_or(0) > 0 => None,
+        _ => Some(PeriodError::AlreadyWithdrawn),
+    }
+}
+
+/// Checks that `from` has a matured, non-withdrawn position in `period`,
+/// listing the periods that can be withdrawn otherwise.
+pub(crate) fn check_period(
+    env: &EnvClient,
+    contract: &str,
+    from: &str,
+    period: i32,
+) -> Result<(), SimulationError> {
+    let config = read_pool(env, contract)?;
+    let user = address_to_scval(from).map_err(|_| SimulationError::InvalidAddress {
+        address: from.to_string(),
+    })?;
+    let user: Address = env.from_scval(&user);
+
+    let balance = config.balance(env, &user, period).map_err(entry_error)?;
+    let fees = config
+        .matured_fees(env, &user, period)
+        .map_err(entry_error)?;
+    match period_error(period, config.current_period(env), balance, fees) {
+        None => Ok(()),
+        Some(reason) => Err(SimulationError::InvalidPeriod {
+            period,
+            reason,
+            valid_periods: config
+                .withdrawable_periods(env, &user)
+                .map_err(entry_error)?,
+        }),
+    }
+}
+
+/// Periods in which `from` has a matured position left to withdraw.
+pub(crate) fn withdrawable_periods(
+    env: &EnvClient,
+    contract: &str,
+    from: &str,
+) -> Result<Vec<i32>, SimulationError> {
+    let config = read_pool(env, contract)?;
+    let user = address_to_scval(from).map_err(|_| SimulationError::InvalidAddress {
+        address: from.to_string(),
+    })?;
+
+    config
+        .withdrawable_periods(env, &env.from_scval(&user))
+        .map_err(entry_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trustline_assets() {
+        let issuer = "GAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAWHF";
+
+        let Some(ChangeTrustAsset::CreditAlphanum4(usdc)) =
+            trustline_asset(&format!("USDC:{}", issuer))
+        else {
+            panic!()
+        };
+        assert_eq!(&usdc.asset_code.0, b"USDC");
+        assert!(matches!(
+            trustline_asset(&format!("LONGERCODE:{}", issuer)),
+            Some(ChangeTrustAsset::CreditAlphanum12(_))
+        ));
+        assert!(trustline_asset("native").is_none());
+        assert!(trustline_asset("USDC:GABC").is_none());
+        assert!(trustline_asset(&format!("WAYTOOLONGCODE:{}", issuer)).is_none());
+    }
+
+    #[test]
+    fn periods() {
+        assert_eq!(
+            period_error(3, 3, Some(10), None),
+            Some(PeriodError::NotMatured)
+        );
+        assert_eq!(period_error(2, 3, None, None), Some(PeriodError::NoBalance));
+        assert_eq!(
+            period_error(2, 3, Some(0), None),
+            Some(PeriodError::AlreadyWithdrawn)
+        );
+        assert_eq!(
+            period_error(2, 3, Some(0), Some(0)),
+            Some(PeriodError::AlreadyWithdrawn)
+        );
+        assert_eq!(period_error(2, 3, Some(10), None), None);
+        assert_eq!(period_error(2, 3, Some(0), Some(5)), None);
+        assert_eq!(period_error(2, 3, None, Some(5)), None);
+    }
+}
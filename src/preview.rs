@@ -0,0 +1,422 @@
+use crate::{
+    amount::{format_amount, OutputOptions},
+    encoding::{from_scval, to_scval},
+    pool::PoolConfig,
+    preflight::{read_token_decimals, token_address},
+    simulation::{address_to_scval, ContractCall},
+    types::*,
+};
+use zephyr_sdk::{
+    soroban_sdk::xdr::{
+        ContractEvent, ContractEventBody, Hash, LedgerEntry, LedgerEntryData, ScAddress, ScSymbol,
+        ScVal,
+    },
+    EnvClient,
+};
+
+/// A pool entry modified by the simulated call, with its value before and
+/// after the call.
+struct EntryChange {
+    key: PersistentDataKey,
+    before: Option<i128>,
+    after: Option<i128>,
+}
+
+impl EntryChange {
+    fn delta(&self) -> i128 {
+        self.after.unwrap_or(0) - self.before.unwrap_or(0)
+    }
+}
+
+fn pool_entry(
+    env: &EnvClient,
+    contract: [u8; 32],
+    entry: &LedgerEntry,
+) -> Option<(PersistentDataKey, Option<i128>)> {
+    let LedgerEntryData::ContractData(data) = &entry.data else {
+        return None;
+    };
+    if data.contract != ScAddress::Contract(Hash(contract)) {
+        return None;
+    }
+    let key = from_scval::<PersistentDataKey>(env, &data.key).ok()?;
+
+    Some((key, from_scval::<i128>(env, &data.val).ok()))
+}
+
+fn entry_changes(
+    env: &EnvClient,
+    contract: [u8; 32],
+    diffs: &[(Option<LedgerEntry>, Option<LedgerEntry>)],
+) -> Vec<EntryChange> {
+    diffs
+        .iter()
+        .filter_map(|(before, after)| {
+            let before = before
+                .as_ref()
+                .and_then(|entry| pool_entry(env, contract, entry));
+            let after = after
+                .as_ref()
+                .and_then(|entry| pool_entry(env, contract, entry));
+            let key = match (&before, &after) {
+                (_, Some((key, _))) | (Some((key, _)), None) => key.clone(),
+                (None, None) => return None,
+            };
+
+            Some(EntryChange {
+                key,
+                before: before.and_then(|(_, value)| value),
+                after: after.and_then(|(_, value)| value),
+            })
+        })
+        .collect()
+}
+
+/// Sums the SEP-41 `transfer` events of `token` moving tokens from `from`
+/// to `to`.
+fn transferred(events: &[ContractEvent], token: [u8; 32], from: &ScVal, to: &ScVal) -> i128 {
+    let transfer = ScVal::Symbol(ScSymbol("transfer".try_into().unwrap()));
+
+    events
+        .iter()
+        .filter_map(|event| {
+            let ContractEventBody::V0(body) = &event.body;
+            let is_transfer = event.contract_id == Some(Hash(token))
+                && body.topics.len() >= 3
+                && body.topics[0] == transfer
+                && &body.topics[1] == from
+                && &body.topics[2] == to;
+            match &body.data {
+                ScVal::I128(parts) if is_transfer => {
+                    Some(((parts.hi as i128) << 64) | parts.lo as i128)
+                }
+                _ => None,
+            }
+        })
+        .sum()
+}
+
+/// Previews how the simulated call changes the caller's position in the
+/// pool, from the entries it modified and the pool token it transferred.
+/// Returns `None` when the call doesn't touch the caller's position or isn't
+/// made to a pool.
+pub(crate) fn position_preview(
+    env: &EnvClient,
+    call: &ContractCall,
+    diffs: &[(Option<LedgerEntry>, Option<LedgerEntry>)],
+    events: &[ContractEvent],
+    output: &OutputOptions,
+) -> Option<PositionPreview> {
+    let config =
+        PoolConfig::read(env, &stellar_strkey::Contract(call.contract).to_string()).ok()?;
+    let token = token_address(&config).ok()?;
+    let decimals = if output.formatted {
+        read_token_decimals(env, &config.token_id)
+    } else {
+        None
+    };
+
+    preview(env, call, token, decimals, diffs, events, output)
+}
+
+fn preview(
+    env: &EnvClient,
+    call: &ContractCall,
+    token: [u8; 32],
+    decimals: Option<u32>,
+    diffs: &[(Option<LedgerEntry>, Option<LedgerEntry>)],
+    events: &[ContractEvent],
+    output: &OutputOptions,
+) -> Option<PositionPreview> {
+    let user = address_to_scval(&call.from).ok()?;
+    let pool = ScVal::Address(ScAddress::Contract(Hash(call.contract)));
+    let is_user = |object: &BalanceObject| to_scval(env, object.address.clone()) == user;
+
+    let mut period = None;
+    let mut shares_minted = 0;
+    let mut balance = None;
+    let mut principal = None;
+    let mut principal_withdrawn = 0;
+    // Totals by period, as the call may also touch periods other than the
+    // caller's.
+    let mut supplies = Vec::new();
+    let mut liquidities = Vec::new();
+    let mut touched = false;
+    for change in entry_changes(env, call.contract, diffs) {
+        match &change.key {
+            PersistentDataKey::Balance(object) if is_user(object) => {
+                period = Some(object.period);
+                shares_minted = change.delta().max(0);
+                balance = change.after;
+                touched = true;
+            }
+            PersistentDataKey::Principal(object) if is_user(object) => {
+                period = Some(object.period);
+                principal = change.after;
+                principal_withdrawn = (-change.delta()).max(0);
+                touched = true;
+            }
+            PersistentDataKey::TotSupply(period) => supplies.push((*period, change.after)),
+            PersistentDataKey::TotLiquidity(period) => liquidities.push((*period, change.after)),
+            _ => {}
+        }
+    }
+
+    let tokens_returned = transferred(events, token, &pool, &user);
+    if !touched && tokens_returned == 0 {
+        return None;
+    }
+    let fees_realized = (tokens_returned - principal_withdrawn).max(0);
+    let total = |totals: &[(i32, Option<i128>)]| {
+        totals
+            .iter()
+            .find(|(total_period, _)| Some(*total_period) == period)
+            .and_then(|(_, value)| *value)
+    };
+    let tot_supply = total(&supplies);
+    let tot_liquidity = total(&liquidities);
+
+    let formatted = decimals.map(|decimals| FormattedPreview {
+        principal: principal.map(|value| format_amount(value, decimals)),
+        tot_liquidity: tot_liquidity.map(|value| format_amount(value, decimals)),
+        tokens_returned: format_amount(tokens_returned, decimals),
+        fees_realized: format_amount(fees_realized, decimals),
+    });
+
+    Some(PositionPreview {
+        period,
+        shares_minted: output.int(shares_minted),
+        balance: balance.map(|value| output.int(value)),
+        principal: principal.map(|value| output.int(value)),
+        tot_supply: tot_supply.map(|value| output.int(value)),
+        tot_liquidity: tot_liquidity.map(|value| output.int(value)),
+        tokens_returned: output.int(tokens_returned),
+        fees_realized: output.int(fees_realized),
+        formatted,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::{Arg, DEPOSIT, WITHDRAW};
+    use zephyr_sdk::soroban_sdk::{
+        xdr::{
+            ContractDataDurability, ContractDataEntry, ContractEventType, ContractEventV0,
+            ExtensionPoint, LedgerEntryExt,
+        },
+        Address, String,
+    };
+
+    const POOL: [u8; 32] = [1; 32];
+    const TOKEN: [u8; 32] = [2; 32];
+    const USER: &str = "GAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAWHF";
+
+    const OUTPUT: OutputOptions = OutputOptions {
+        encoding: IntEncoding::Number,
+        formatted: false,
+    };
+
+    fn event(contract: [u8; 32], topics: Vec<ScVal>, amount: i128) -> ContractEvent {
+        ContractEvent {
+            ext: ExtensionPoint::V0,
+            contract_id: Some(Hash(contract)),
+            type_: ContractEventType::Contract,
+            body: ContractEventBody::V0(ContractEventV0 {
+                topics: topics.try_into().unwrap(),
+                data: ScVal::from(amount),
+            }),
+        }
+    }
+
+    fn entry(
+        env: &EnvClient,
+        contract: [u8; 32],
+        key: PersistentDataKey,
+        value: i128,
+    ) -> LedgerEntry {
+        LedgerEntry {
+            last_modified_ledger_seq: 0,
+            data: LedgerEntryData::ContractData(ContractDataEntry {
+                ext: ExtensionPoint::V0,
+                contract: ScAddress::Contract(Hash(contract)),
+                key: to_scval(env, key),
+                durability: ContractDataDurability::Persistent,
+                val: ScVal::from(value),
+            }),
+            ext: LedgerEntryExt::V0,
+        }
+    }
+
+    fn user_key(env: &EnvClient, period: i32) -> BalanceObject {
+        BalanceObject {
+            address: Address::from_string(&String::from_str(env.soroban(), USER)),
+            period,
+        }
+    }
+
+    type Diff = (Option<LedgerEntry>, Option<LedgerEntry>);
+
+    fn diff(
+        env: &EnvClient,
+        key: PersistentDataKey,
+        before: Option<i128>,
+        after: Option<i128>,
+    ) -> Diff {
+        (
+            before.map(|value| entry(env, POOL, key.clone(), value)),
+            after.map(|value| entry(env, POOL, key, value)),
+        )
+    }
+
+    fn deposit_diffs(env: &EnvClient) -> Vec<Diff> {
+        vec![
+            diff(
+                env,
+                PersistentDataKey::Balance(user_key(env, 3)),
+                None,
+                Some(100),
+            ),
+            diff(
+                env,
+                PersistentDataKey::Principal(user_key(env, 3)),
+                None,
+                Some(100),
+            ),
+            diff(env, PersistentDataKey::TotSupply(3), Some(500), Some(600)),
+            diff(
+                env,
+                PersistentDataKey::TotLiquidity(2),
+                Some(900),
+                Some(950),
+            ),
+            diff(
+                env,
+                PersistentDataKey::TotLiquidity(3),
+                Some(500),
+                Some(600),
+            ),
+            (
+                None,
+                Some(entry(env, TOKEN, PersistentDataKey::TotSupply(3), 7)),
+            ),
+        ]
+    }
+
+    #[test]
+    fn transfers() {
+        let transfer = ScVal::Symbol(ScSymbol("transfer".try_into().unwrap()));
+        let pool = ScVal::Address(ScAddress::Contract(Hash(POOL)));
+        let user = address_to_scval(USER).unwrap();
+
+        let paid = vec![transfer.clone(), pool.clone(), user.clone()];
+        let received = vec![transfer, user.clone(), pool.clone()];
+
+        let events = [
+            event(TOKEN, paid.clone(), 70),
+            event(TOKEN, received, 1000),
+            event(TOKEN, paid.clone(), 30),
+            event([3; 32], paid, 500),
+        ];
+        assert_eq!(transferred(&events, TOKEN, &pool, &user), 100);
+        assert_eq!(transferred(&events, TOKEN, &user, &pool), 1000);
+    }
+
+    #[test]
+    fn deposit_preview() {
+        let env = EnvClient::empty();
+        let diffs = deposit_diffs(&env);
+
+        let changes = entry_changes(&env, POOL, &diffs);
+        assert_eq!(changes.len(), 5);
+        assert!(matches!(changes[0].key, PersistentDataKey::Balance(_)));
+        assert_eq!((changes[0].before, changes[0].after), (None, Some(100)));
+        assert_eq!(changes[2].delta(), 100);
+
+        let pool = stellar_strkey::Contract(POOL).to_string();
+        let call = ContractCall::new(
+            &pool,
+            USER,
+            &DEPOSIT,
+            vec![Arg::Address(USER.to_string()), Arg::I128(100)],
+        )
+        .unwrap();
+        let position = preview(&env, &call, TOKEN, None, &diffs, &[], &OUTPUT).unwrap();
+        assert_eq!(position.period, Some(3));
+        assert_eq!(position.shares_minted, JsonInt::Number(100));
+        assert_eq!(position.balance, Some(JsonInt::Number(100)));
+        assert_eq!(position.tot_supply, Some(JsonInt::Number(600)));
+        assert_eq!(position.tot_liquidity, Some(JsonInt::Number(600)));
+        assert_eq!(position.tokens_returned, JsonInt::Number(0));
+
+        let other = stellar_strkey::ed25519::PublicKey([1; 32]).to_string();
+        let call = ContractCall::new(
+            &pool,
+            &other,
+            &DEPOSIT,
+            vec![Arg::Address(other.clone()), Arg::I128(100)],
+        )
+        .unwrap();
+        assert!(preview(&env, &call, TOKEN, None, &diffs, &[], &OUTPUT).is_none());
+    }
+
+    #[test]
+    fn withdraw_preview() {
+        let env = EnvClient::empty();
+        let diffs = vec![
+            diff(
+                &env,
+                PersistentDataKey::Balance(user_key(&env, 3)),
+                Some(100),
+                None,
+            ),
+            diff(
+                &env,
+                PersistentDataKey::Principal(user_key(&env, 3)),
+                Some(100),
+                None,
+            ),
+            diff(&env, PersistentDataKey::TotSupply(3), Some(600), Some(500)),
+            diff(
+                &env,
+                PersistentDataKey::TotLiquidity(3),
+                Some(620),
+                Some(500),
+            ),
+        ];
+
+        let changes = entry_changes(&env, POOL, &diffs);
+        assert_eq!(changes.len(), 4);
+        assert_eq!((changes[1].before, changes[1].after), (Some(100), None));
+        assert_eq!(changes[3].delta(), -120);
+
+        let transfer = ScVal::Symbol(ScSymbol("transfer".try_into().unwrap()));
+        let pool = ScVal::Address(ScAddress::Contract(Hash(POOL)));
+        let user = address_to_scval(USER).unwrap();
+        let events = [
+            event(
+                TOKEN,
+                vec![transfer.clone(), pool.clone(), user.clone()],
+                120,
+            ),
+            event([3; 32], vec![transfer, pool, user], 999),
+        ];
+
+        let call = ContractCall::new(
+            &stellar_strkey::Contract(POOL).to_string(),
+            USER,
+            &WITHDRAW,
+            vec![Arg::Address(USER.to_string()), Arg::I32(3)],
+        )
+        .unwrap();
+        let position = preview(&env, &call, TOKEN, Some(2), &diffs, &events, &OUTPUT).unwrap();
+        assert_eq!(position.period, Some(3));
+        assert_eq!(position.shares_minted, JsonInt::Number(0));
+        assert_eq!(position.balance, None);
+        assert_eq!(position.principal, None);
+        assert_eq!(position.tot_supply, Some(JsonInt::Number(500)));
+        assert_eq!(position.tokens_returned, JsonInt::Number(120));
+        assert_eq!(position.fees_realized, JsonInt::Number(20));
+        assert_eq!(position.formatted.unwrap().fees_realized, "0.2");
+    }
+}
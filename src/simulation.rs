@@ -0,0 +1,1270 @@
+use crate::{
+    amount::OutputOptions,
+    config::{DEFAULT_PADDING, NETWORK_PASSPHRASE},
+    deploy,
+    encoding::{decode_hex, to_hex},
+    preflight, preview, transaction,
+    types::*,
+};
+use stellar_strkey::Strkey;
+use zephyr_sdk::{
+    prelude::*,
+    soroban_sdk::xdr::{
+        AccountId, Hash, HostFunction, InvokeContractArgs, InvokeHostFunctionOp, MuxedAccount,
+        MuxedAccountMed25519, Operation, OperationBody, PublicKey, ScAddress, ScBytes, ScError,
+        ScMap, ScMapEntry, ScSymbol, ScVal, ScVec, SequenceNumber, Transaction,
+        TransactionEnvelope, TransactionExt, TransactionSignaturePayloadTaggedTransaction,
+        TransactionV1Envelope, Uint256,
+    },
+    EnvClient,
+};
+
+/// Shape of an argument taken by a pool function.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum ArgShape {
+    Address,
+    I128,
+    I32,
+    Symbol,
+    Hash,
+    Bool,
+}
+
+/// A pool contract function that can be simulated.
+pub(crate) struct PoolFunction {
+    pub name: &'static str,
+    /// Declared arguments, checked against every call to the function.
+    pub args: &'static [ArgShape],
+    /// Whether only the pool admin can call the function.
+    pub admin: bool,
+}
+
+// Pool functions exposed through `simulate`, along with their arguments.
+
+pub(crate) const DEPOSIT: PoolFunction = PoolFunction {
+    name: "deposit",
+    args: &[ArgShape::Address, ArgShape::I128],
+    admin: false,
+};
+
+pub(crate) const UPDATE_FEE_REWARDS: PoolFunction = PoolFunction {
+    name: "update_fee_rewards",
+    args: &[ArgShape::Address, ArgShape::I32],
+    admin: false,
+};
+
+pub(crate) const WITHDRAW_MATURED: PoolFunction = PoolFunction {
+    name: "withdraw_matured",
+    args: &[ArgShape::Address, ArgShape::I32],
+    admin: false,
+};
+
+pub(crate) const WITHDRAW: PoolFunction = PoolFunction {
+    name: "withdraw",
+    args: &[ArgShape::Address, ArgShape::I32],
+    admin: false,
+};
+
+pub(crate) const SUBSCRIBE: PoolFunction = PoolFunction {
+    name: "subscribe",
+    args: &[ArgShape::Address, ArgShape::I128],
+    admin: false,
+};
+
+pub(crate) const CLAIM_REWARD: PoolFunction = PoolFunction {
+    name: "claim_reward",
+    args: &[ArgShape::Address],
+    admin: false,
+};
+
+pub(crate) const SET_MULTIPLIER: PoolFunction = PoolFunction {
+    name: "set_multiplier",
+    args: &[ArgShape::I32],
+    admin: true,
+};
+
+pub(crate) const SET_VOLATILITY: PoolFunction = PoolFunction {
+    name: "set_volatility",
+    args: &[ArgShape::I128],
+    admin: true,
+};
+
+pub(crate) const SET_ORACLE: PoolFunction = PoolFunction {
+    name: "set_oracle",
+    args: &[ArgShape::Address, ArgShape::Symbol],
+    admin: true,
+};
+
+pub(crate) const SET_ADMIN: PoolFunction = PoolFunction {
+    name: "set_admin",
+    args: &[ArgShape::Address],
+    admin: true,
+};
+
+pub(crate) const UPGRADE: PoolFunction = PoolFunction {
+    name: "upgrade",
+    args: &[ArgShape::Hash],
+    admin: true,
+};
+
+#[derive(Clone, Debug)]
+pub(crate) enum Arg {
+    Address(String),
+    I128(i128),
+    I32(i32),
+    Symbol(String),
+    Hash(String),
+    Bool(bool),
+}
+
+impl Arg {
+    pub(crate) fn shape(&self) -> ArgShape {
+        match self {
+            Self::Address(_) => ArgShape::Address,
+            Self::I128(_) => ArgShape::I128,
+            Self::I32(_) => ArgShape::I32,
+            Self::Symbol(_) => ArgShape::Symbol,
+            Self::Hash(_) => ArgShape::Hash,
+            Self::Bool(_) => ArgShape::Bool,
+        }
+    }
+
+    pub(crate) fn to_scval(&self) -> Result<ScVal, SimulationError> {
+        let invalid = |reason| SimulationError::InvalidArgument { reason };
+
+        match self {
+            Self::Address(address) => {
+                address_to_scval(address).map_err(|_| SimulationError::InvalidAddress {
+                    address: address.clone(),
+                })
+            }
+            Self::I128(value) => Ok(ScVal::from(*value)),
+            Self::I32(value) => Ok(ScVal::I32(*value)),
+            Self::Bool(value) => Ok(ScVal::Bool(*value)),
+            Self::Symbol(symbol) => JsonArg::Symbol(symbol.clone()).to_scval().map_err(invalid),
+            Self::Hash(hash) => {
+                let bytes = decode_hex(hash).map_err(invalid)?;
+                if bytes.len() != 32 {
+                    return Err(invalid(format!("invalid hash {}", hash)));
+                }
+                Ok(ScVal::Bytes(ScBytes(bytes.try_into().unwrap())))
+            }
+        }
+    }
+}
+
+/// Converts a G..., M... or C... strkey into an address `ScVal`. Muxed
+/// accounts resolve to their underlying account, as contracts can't tell
+/// them apart.
+pub(crate) fn address_to_scval(address: &str) -> Result<ScVal, String> {
+    let address = match stellar_strkey::Strkey::from_string(address) {
+        Ok(Strkey::PublicKeyEd25519(key)) => {
+            ScAddress::Account(AccountId(PublicKey::PublicKeyTypeEd25519(Uint256(key.0))))
+        }
+        Ok(Strkey::MuxedAccountEd25519(muxed)) => ScAddress::Account(AccountId(
+            PublicKey::PublicKeyTypeEd25519(Uint256(muxed.ed25519)),
+        )),
+        Ok(Strkey::Contract(contract)) => ScAddress::Contract(Hash(contract.0)),
+        _ => return Err(format!("invalid address {}", address)),
+    };
+
+    Ok(ScVal::Address(address))
+}
+
+impl JsonArg {
+    /// Converts the typed JSON argument into its `ScVal` representation.
+    pub(crate) fn to_scval(&self) -> Result<ScVal, String> {
+        let val = match self {
+            Self::Address(address) => address_to_scval(address)?,
+            Self::I128(value) => ScVal::from(
+                value
+                    .parse::<i128>()
+                    .map_err(|_| format!("invalid i128 {}", value))?,
+            ),
+            Self::U32(value) => ScVal::U32(*value),
+            Self::I32(value) => ScVal::I32(*value),
+            Self::Bool(value) => ScVal::Bool(*value),
+            Self::Symbol(symbol) => ScVal::Symbol(ScSymbol(
+                symbol
+                    .as_str()
+                    .try_into()
+                    .map_err(|_| format!("invalid symbol {}", symbol))?,
+            )),
+            Self::Bytes(hex) => ScVal::Bytes(ScBytes(
+                decode_hex(hex)?
+                    .try_into()
+                    .map_err(|_| format!("bytes too long {}", hex))?,
+            )),
+            Self::Vec(items) => {
+                let items = items
+                    .iter()
+                    .map(Self::to_scval)
+                    .collect::<Result<Vec<_>, _>>()?;
+                ScVal::Vec(Some(ScVec(items.try_into().map_err(|_| "vec too long")?)))
+            }
+            Self::Map(entries) => {
+                let mut entries = entries
+                    .iter()
+                    .map(|entry| {
+                        Ok(ScMapEntry {
+                            key: entry.key.to_scval()?,
+                            val: entry.value.to_scval()?,
+                        })
+                    })
+                    .collect::<Result<Vec<_>, String>>()?;
+                // soroban maps must be sorted by key.
+                entries.sort_by(|a, b| a.key.cmp(&b.key));
+                ScVal::Map(Some(ScMap(entries.try_into().map_err(|_| "map too long")?)))
+            }
+        };
+
+        Ok(val)
+    }
+}
+
+fn contract_address(contract: &str) -> Result<[u8; 32], SimulationError> {
+    stellar_strkey::Contract::from_string(contract)
+        .map(|contract| contract.0)
+        .map_err(|_| SimulationError::InvalidAddress {
+            address: contract.to_string(),
+        })
+}
+
+/// A contract function call made by `from`.
+pub(crate) struct ContractCall {
+    pub contract: [u8; 32],
+    pub from: String,
+    pub function: String,
+    pub args: Vec<ScVal>,
+    /// Whether `from` must be the pool admin.
+    pub admin: bool,
+    /// Salt of the pool deployed by a factory call.
+    pub deploy_salt: Option<[u8; 32]>,
+}
+
+impl ContractCall {
+    /// Builds a call to the pool `function`, rejecting arguments that don't
+    /// match its declared shape.
+    pub(crate) fn new(
+        contract: &str,
+        from: &str,
+        function: &'static PoolFunction,
+        args: Vec<Arg>,
+    ) -> Result<Self, SimulationError> {
+        if !args
+            .iter()
+            .map(Arg::shape)
+            .eq(function.args.iter().copied())
+        {
+            return Err(SimulationError::InvalidArgument {
+                reason: format!("invalid arguments for {}", function.name),
+            });
+        }
+
+        Ok(Self {
+            contract: contract_address(contract)?,
+            from: from.to_string(),
+            function: function.name.to_string(),
+            args: args.iter().map(Arg::to_scval).collect::<Result<_, _>>()?,
+            admin: function.admin,
+            deploy_salt: None,
+        })
+    }
+
+    /// Builds a call to an arbitrary contract function.
+    pub(crate) fn custom(
+        contract: &str,
+        from: &str,
+        name: &str,
+        args: &[JsonArg],
+    ) -> Result<Self, SimulationError> {
+        if ScSymbol::try_from(name).is_err() {
+            return Err(SimulationError::InvalidArgument {
+                reason: format!("invalid function name {}", name),
+            });
+        }
+
+        Ok(Self {
+            contract: contract_address(contract)?,
+            from: from.to_string(),
+            function: name.to_string(),
+            args: args
+                .iter()
+                .map(|arg| {
+                    arg.to_scval()
+                        .map_err(|reason| SimulationError::InvalidArgument { reason })
+                })
+                .collect::<Result<_, _>>()?,
+            admin: false,
+            deploy_salt: None,
+        })
+    }
+}
+
+impl SimulationRequest {
+    /// Maps the request to the pool function call it simulates.
+    pub(crate) fn call(&self) -> Result<ContractCall, SimulationError> {
+        match self {
+            Self::Deposit(SimulateDeposit {
+                contract,
+                from,
+                amount,
+            }) => ContractCall::new(
+                contract,
+                from,
+                &DEPOSIT,
+                vec![
+                    Arg::Address(from.clone()),
+                    Arg::I128(amount.to_stroops(None)?),
+                ],
+            ),
+            Self::UpdateFeeRewards(SimulateUpdateFeeRewards {
+                contract,
+                from,
+                period,
+            }) => ContractCall::new(
+                contract,
+                from,
+                &UPDATE_FEE_REWARDS,
+                vec![Arg::Address(from.clone()), Arg::I32(*period)],
+            ),
+            Self::WithdrawMatured(SimulateWithdrawMatured {
+                contract,
+                from,
+                period,
+            }) => ContractCall::new(
+                contract,
+                from,
+                &WITHDRAW_MATURED,
+                vec![Arg::Address(from.clone()), Arg::I32(*period)],
+            ),
+            Self::Withdraw(SimulateWithdraw {
+                contract,
+                from,
+                period,
+            }) => ContractCall::new(
+                contract,
+                from,
+                &WITHDRAW,
+                vec![Arg::Address(from.clone()), Arg::I32(*period)],
+            ),
+            Self::Subscribe(SimulateSubscribe {
+                contract,
+                from,
+                amount,
+            }) => ContractCall::new(
+                contract,
+                from,
+                &SUBSCRIBE,
+                vec![
+                    Arg::Address(from.clone()),
+                    Arg::I128(amount.to_stroops(None)?),
+                ],
+            ),
+            Self::ClaimReward(SimulateClaimReward { contract, from }) => ContractCall::new(
+                contract,
+                from,
+                &CLAIM_REWARD,
+                vec![Arg::Address(from.clone())],
+            ),
+            Self::Custom(SimulateCustom {
+                contract,
+                from,
+                function,
+                args,
+            }) => ContractCall::custom(contract, from, function, args),
+            Self::SetMultiplier(SimulateSetMultiplier {
+                contract,
+                from,
+                multiplier,
+            }) => ContractCall::new(contract, from, &SET_MULTIPLIER, vec![Arg::I32(*multiplier)]),
+            Self::SetVolatility(SimulateSetVolatility {
+                contract,
+                from,
+                volatility,
+            }) => ContractCall::new(
+                contract,
+                from,
+                &SET_VOLATILITY,
+                vec![Arg::I128(*volatility)],
+            ),
+            Self::SetOracle(SimulateSetOracle {
+                contract,
+                from,
+                oracle,
+                oracle_asset,
+            }) => ContractCall::new(
+                contract,
+                from,
+                &SET_ORACLE,
+                vec![
+                    Arg::Address(oracle.clone()),
+                    Arg::Symbol(oracle_asset.clone()),
+                ],
+            ),
+            Self::SetAdmin(SimulateSetAdmin {
+                contract,
+                from,
+                admin,
+            }) => ContractCall::new(
+                contract,
+                from,
+                &SET_ADMIN,
+                vec![Arg::Address(admin.clone())],
+            ),
+            Self::Upgrade(SimulateUpgrade {
+                contract,
+                from,
+                wasm_hash,
+            }) => ContractCall::new(contract, from, &UPGRADE, vec![Arg::Hash(wasm_hash.clone())]),
+            Self::DeployPool(request) => ContractCall::deploy(request),
+            Self::WithdrawAllMatured(_) => Err(SimulationError::InvalidArgument {
+                reason: "WithdrawAllMatured spans several transactions, use simulate_batch"
+                    .to_string(),
+            }),
+        }
+    }
+
+    /// Maps the request to the prepared calls of a batch. Withdrawing all
+    /// matured positions expands into `update_fee_rewards` and
+    /// `withdraw_matured` for every withdrawable period, and other requests
+    /// into their single call.
+    pub(crate) fn prepare_batch(
+        &self,
+        env: &EnvClient,
+        options: &SimulationOptions,
+    ) -> Vec<Result<ContractCall, SimulationError>> {
+        let Self::WithdrawAllMatured(SimulateWithdrawAllMatured { contract, from }) = self else {
+            return vec![self.prepare(env, options)];
+        };
+        let periods = match preflight::withdrawable_periods(env, contract, from) {
+            Ok(periods) => periods,
+            Err(error) => return vec![Err(error)],
+        };
+
+        periods
+            .into_iter()
+            .flat_map(|period| {
+                [
+                    Self::UpdateFeeRewards(SimulateUpdateFeeRewards {
+                        contract: contract.clone(),
+                        from: from.clone(),
+                        period,
+                    }),
+                    Self::WithdrawMatured(SimulateWithdrawMatured {
+                        contract: contract.clone(),
+                        from: from.clone(),
+                        period,
+                    }),
+                ]
+            })
+            .map(|request| request.prepare(env, options))
+            .collect()
+    }
+
+    /// Maps the request to its call, then validates it against the ledger.
+    /// Runs before the transaction source is resolved, so that callers get
+    /// the validation error rather than a missing source account.
+    pub(crate) fn prepare(
+        &self,
+        env: &EnvClient,
+        options: &SimulationOptions,
+    ) -> Result<ContractCall, SimulationError> {
+        let resolved = self.resolve_amount(env)?;
+        let request = resolved.as_ref().unwrap_or(self);
+        let call = request.call()?;
+
+        match request {
+            Self::DeployPool(request) => request.validate(env)?,
+            Self::Deposit(SimulateDeposit {
+                contract,
+                from,
+                amount,
+            })
+            | Self::Subscribe(SimulateSubscribe {
+                contract,
+                from,
+                amount,
+            }) => {
+                let amount = amount.to_stroops(None)?;
+                preflight::check_balance(env, contract, from, amount, options)?
+            }
+            Self::Withdraw(SimulateWithdraw {
+                contract,
+                from,
+                period,
+            })
+            | Self::WithdrawMatured(SimulateWithdrawMatured {
+                contract,
+                from,
+                period,
+            }) => preflight::check_period(env, contract, from, *period)?,
+            _ => {}
+        }
+
+        call.check_admin(env)?;
+
+        Ok(call)
+    }
+
+    /// Converts a decimal amount to stroops using the decimals of the pool's
+    /// token. Returns `None` when the request has nothing to convert.
+    fn resolve_amount(&self, env: &EnvClient) -> Result<Option<Self>, SimulationError> {
+        let (contract, amount) = match self {
+            Self::Deposit(request) => (&request.contract, &request.amount),
+            Self::Subscribe(request) => (&request.contract, &request.amount),
+            _ => return Ok(None),
+        };
+        if !amount.is_decimal() {
+            return Ok(None);
+        }
+        let decimals = preflight::token_decimals(env, contract)?;
+        let amount = Amount::Text(amount.to_stroops(Some(decimals))?.to_string());
+
+        Ok(Some(match self {
+            Self::Deposit(request) => Self::Deposit(SimulateDeposit {
+                amount,
+                ..request.clone()
+            }),
+            Self::Subscribe(request) => Self::Subscribe(SimulateSubscribe {
+                amount,
+                ..request.clone()
+            }),
+            _ => unreachable!(),
+        }))
+    }
+}
+
+/// Resolved padding applied to a simulated transaction: percentage margins
+/// first, then absolute increments.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct PaddingPolicy {
+    pub instructions: u32,
+    pub read_bytes: u32,
+    pub write_bytes: u32,
+    pub resource_fee: i64,
+    pub instructions_pct: u32,
+    pub read_bytes_pct: u32,
+    pub write_bytes_pct: u32,
+    pub resource_fee_pct: u32,
+    pub inclusion_fee: u32,
+}
+
+fn pad_u32(value: u32, pct: u32, increment: u32) -> u32 {
+    let margin = value as u64 * pct as u64 / 100;
+    (value as u64 + margin + increment as u64).min(u32::MAX as u64) as u32
+}
+
+impl PaddingPolicy {
+    /// Overrides the server defaults with the fields set in the request.
+    pub(crate) fn from_request(padding: Option<&Padding>) -> Self {
+        let default = DEFAULT_PADDING;
+        let Some(padding) = padding else {
+            return default;
+        };
+
+        Self {
+            instructions: padding.instructions.unwrap_or(default.instructions),
+            read_bytes: padding.read_bytes.unwrap_or(default.read_bytes),
+            write_bytes: padding.write_bytes.unwrap_or(default.write_bytes),
+            resource_fee: padding.resource_fee.unwrap_or(default.resource_fee),
+            instructions_pct: padding.instructions_pct.unwrap_or(default.instructions_pct),
+            read_bytes_pct: padding.read_bytes_pct.unwrap_or(default.read_bytes_pct),
+            write_bytes_pct: padding.write_bytes_pct.unwrap_or(default.write_bytes_pct),
+            resource_fee_pct: padding.resource_fee_pct.unwrap_or(default.resource_fee_pct),
+            inclusion_fee: padding.inclusion_fee.unwrap_or(default.inclusion_fee),
+        }
+    }
+
+    /// Pads the resources and resource fee, and sets the transaction fee to
+    /// the inclusion fee bid plus the padded resource fee.
+    pub(crate) fn apply(&self, tx: &mut Transaction) -> Result<(), SimulationError> {
+        let TransactionExt::V1(v1ext) = &mut tx.ext else {
+            return Err(SimulationError::UnexpectedEnvelope);
+        };
+        let r = &mut v1ext.resources;
+        r.instructions = pad_u32(r.instructions, self.instructions_pct, self.instructions);
+        r.read_bytes = pad_u32(r.read_bytes, self.read_bytes_pct, self.read_bytes);
+        r.write_bytes = pad_u32(r.write_bytes, self.write_bytes_pct, self.write_bytes);
+
+        let fee = v1ext.resource_fee;
+        v1ext.resource_fee = fee
+            .saturating_add(fee.saturating_mul(self.resource_fee_pct as i64) / 100)
+            .saturating_add(self.resource_fee);
+
+        let total = v1ext.resource_fee.saturating_add(self.inclusion_fee as i64);
+        tx.fee = total.clamp(0, u32::MAX as i64) as u32;
+        Ok(())
+    }
+}
+
+fn to_base64(xdr: &impl WriteXdr) -> String {
+    xdr.to_xdr_base64(Limits::none()).unwrap()
+}
+
+impl ContractCall {
+    fn host_function(&self) -> HostFunction {
+        HostFunction::InvokeContract(InvokeContractArgs {
+            contract_address: ScAddress::Contract(Hash(self.contract)),
+            function_name: ScSymbol(self.function.as_str().try_into().unwrap()),
+            args: self.args.clone().try_into().unwrap(),
+        })
+    }
+}
+
+/// Account that sources, sequences and pays for a transaction.
+pub(crate) struct TxSource {
+    pub account: [u8; 32],
+    pub muxed: MuxedAccount,
+}
+
+impl TxSource {
+    /// Resolves the transaction source for an action made by `from`, or the
+    /// explicit `source` account when a relayer submits on the user's behalf.
+    pub(crate) fn resolve(from: &str, source: Option<&str>) -> Result<Self, SimulationError> {
+        match source {
+            Some(source) => Self::account(source),
+            None => Self::for_address(from),
+        }
+    }
+
+    /// Resolves an account that sources or pays for a transaction on its own,
+    /// which can't be a contract.
+    pub(crate) fn account(address: &str) -> Result<Self, SimulationError> {
+        if matches!(Strkey::from_string(address), Ok(Strkey::Contract(_))) {
+            return Err(SimulationError::InvalidArgument {
+                reason: format!("transaction source {} must be an account", address),
+            });
+        }
+
+        Self::for_address(address)
+    }
+
+    /// Resolves the transaction source for an action made by `from`. Accounts
+    /// and muxed accounts are their own source, while contract wallets are
+    /// authorized through auth entries and need an explicit `source` account.
+    pub(crate) fn for_address(from: &str) -> Result<Self, SimulationError> {
+        let invalid = || SimulationError::InvalidAddress {
+            address: from.to_string(),
+        };
+
+        match Strkey::from_string(from).map_err(|_| invalid())? {
+            Strkey::PublicKeyEd25519(key) => Ok(Self {
+                account: key.0,
+                muxed: MuxedAccount::Ed25519(Uint256(key.0)),
+            }),
+            Strkey::MuxedAccountEd25519(muxed) => Ok(Self {
+                account: muxed.ed25519,
+                muxed: MuxedAccount::MuxedEd25519(MuxedAccountMed25519 {
+                    id: muxed.id,
+                    ed25519: Uint256(muxed.ed25519),
+                }),
+            }),
+            Strkey::Contract(_) => Err(SimulationError::InvalidArgument {
+                reason: "contract wallets require a source account".to_string(),
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+fn contract_error_code(error: &ScVal) -> Option<u32> {
+    match error {
+        ScVal::Error(ScError::Contract(code)) => Some(*code),
+        _ => None,
+    }
+}
+
+/// Request-wide settings of a simulation.
+pub(crate) struct SimulationOptions {
+    pub padding: PaddingPolicy,
+    pub source: Option<String>,
+    pub fee_bump: Option<FeeBump>,
+    pub output: OutputOptions,
+    pub memo: Option<TxMemo>,
+    pub bounds: Option<Bounds>,
+    pub sep7: Option<Sep7>,
+    pub network_passphrase: String,
+}
+
+impl SimulationOptions {
+    /// Reads the options of a request, encoding i128 values with `encoding`
+    /// unless the request picks one.
+    pub(crate) fn new(options: &TxOptions, encoding: IntEncoding) -> Self {
+        Self {
+            padding: PaddingPolicy::from_request(options.padding.as_ref()),
+            source: options.source.clone(),
+            fee_bump: options.fee_bump.clone(),
+            output: OutputOptions::resolve(options.output, encoding),
+            memo: options.memo.clone(),
+            bounds: options.bounds.clone(),
+            sep7: options.sep7.clone(),
+            network_passphrase: options
+                .network_passphrase
+                .clone()
+                .unwrap_or_else(|| NETWORK_PASSPHRASE.to_string()),
+        }
+    }
+}
+
+impl ContractCall {
+    /// Checks that `from` is the stored admin of the pool for admin-only
+    /// calls, so that other callers get an error instead of a transaction
+    /// that fails on submission.
+    fn check_admin(&self, env: &EnvClient) -> Result<(), SimulationError> {
+        if !self.admin {
+            return Ok(());
+        }
+        let from = address_to_scval(&self.from).map_err(|_| SimulationError::InvalidAddress {
+            address: self.from.clone(),
+        })?;
+        let address = stellar_strkey::Contract(self.contract).to_string();
+        let config = preflight::read_pool(env, &address)?;
+
+        if address_to_scval(&config.admin).ok() != Some(from) {
+            return Err(SimulationError::NotAdmin {
+                from: self.from.clone(),
+                admin: config.admin,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads the current sequence number of the transaction source.
+pub(crate) fn read_sequence(env: &EnvClient, account: [u8; 32]) -> Result<i64, SimulationError> {
+    let entry = env
+        .read_account_from_ledger(account)
+        .ok()
+        .flatten()
+        .ok_or_else(|| SimulationError::AccountNotFound {
+            account: stellar_strkey::ed25519::PublicKey(account).to_string(),
+        })?;
+
+    Ok(entry.seq_num as i64)
+}
+
+/// Simulates `call` and returns the transaction with padded resources, ready
+/// to be signed by the transaction source (and by `call.from` through its auth
+/// entries when it isn't the source), along with what the simulation reported.
+pub(crate) fn simulate_call(
+    env: &EnvClient,
+    call: ContractCall,
+    options: &SimulationOptions,
+) -> Result<Response, SimulationError> {
+    let source = TxSource::resolve(&call.from, options.source.as_deref())?;
+    let sequence = read_sequence(env, source.account)?;
+
+    simulate_with_sequence(env, call, options, source, sequence + 1)
+}
+
+/// Transaction source account shared by the valid items of a batch, or
+/// `None` when none is valid.
+fn batch_account<T>(
+    items: &[Result<(T, TxSource), SimulationError>],
+) -> Result<Option<[u8; 32]>, SimulationError> {
+    let mut accounts = items.iter().flatten().map(|(_, source)| source.account);
+    let Some(account) = accounts.next() else {
+        return Ok(None);
+    };
+    if accounts.any(|other| other != account) {
+        return Err(SimulationError::InvalidArgument {
+            reason: "all batch items must share the same transaction source".to_string(),
+        });
+    }
+
+    Ok(Some(account))
+}
+
+/// Simulates the items of a batch in order, giving each successful item the
+/// sequence number following the previous successful one. Failed items
+/// don't use up a sequence number.
+fn chain_sequences<T, R>(
+    items: Vec<Result<(T, TxSource), SimulationError>>,
+    mut sequence: i64,
+    mut simulate: impl FnMut(T, TxSource, i64) -> Result<R, SimulationError>,
+) -> Vec<Result<R, SimulationError>> {
+    items
+        .into_iter()
+        .map(|item| {
+            let (call, source) = item?;
+            let response = simulate(call, source, sequence + 1)?;
+            sequence += 1;
+            Ok(response)
+        })
+        .collect()
+}
+
+/// Simulates every call of a batch sharing the same transaction source. The
+/// source's sequence is read once, and each successful transaction takes the
+/// next sequence number so that the batch can be submitted in order. Calls
+/// are simulated against the current ledger, not on top of each other.
+pub(crate) fn simulate_batch(
+    env: &EnvClient,
+    calls: Vec<Result<ContractCall, SimulationError>>,
+    options: &SimulationOptions,
+) -> Result<Vec<Result<Response, SimulationError>>, SimulationError> {
+    let items: Vec<Result<(ContractCall, TxSource), SimulationError>> = calls
+        .into_iter()
+        .map(|call| {
+            let call = call?;
+            let source = TxSource::resolve(&call.from, options.source.as_deref())?;
+            Ok((call, source))
+        })
+        .collect();
+
+    let sequence = match batch_account(&items)? {
+        Some(account) => read_sequence(env, account)?,
+        None => 0,
+    };
+
+    Ok(chain_sequences(
+        items,
+        sequence,
+        |call, source, new_sequence| {
+            simulate_with_sequence(env, call, options, source, new_sequence)
+        },
+    ))
+}
+
+fn simulate_with_sequence(
+    env: &EnvClient,
+    call: ContractCall,
+    options: &SimulationOptions,
+    source: TxSource,
+    new_sequence: i64,
+) -> Result<Response, SimulationError> {
+    let host_function = call.host_function();
+    let simulation = env
+        .simulate(source.account, host_function.clone())
+        .map_err(|error| SimulationError::HostError {
+            reason: error.to_string(),
+        })?;
+    let return_value = match simulation.invoke_result {
+        Ok(value) => value,
+        Err(error) => {
+            return Err(SimulationError::SimulationFailed {
+                contract_error_code: contract_error_code(&error),
+                error: to_base64(&error),
+                diagnostic_events: simulation.diagnostic_events.iter().map(to_base64).collect(),
+            });
+        }
+    };
+    let transaction_data = simulation
+        .transaction_data
+        .ok_or(SimulationError::UnexpectedEnvelope)?;
+    let min_resource_fee = transaction_data.resource_fee;
+
+    let mut tx = Transaction {
+        source_account: source.muxed,
+        fee: 0,
+        seq_num: SequenceNumber(new_sequence),
+        cond: transaction::preconditions(
+            options.bounds.as_ref(),
+            env.soroban().ledger().timestamp(),
+        )?,
+        memo: transaction::memo(options.memo.as_ref())?,
+        operations: std::vec![Operation {
+            source_account: None,
+            body: OperationBody::InvokeHostFunction(InvokeHostFunctionOp {
+                host_function,
+                auth: simulation.auth.clone().try_into().unwrap(),
+            }),
+        }]
+        .try_into()
+        .unwrap(),
+        ext: TransactionExt::V1(transaction_data),
+    };
+    options.padding.apply(&mut tx)?;
+
+    let TransactionExt::V1(v1ext) = &tx.ext else {
+        unreachable!("padding only applies to soroban transactions")
+    };
+    let resources = v1ext.resources.clone();
+    let fee_bump = options
+        .fee_bump
+        .as_ref()
+        .map(|fee_bump| {
+            transaction::fee_bump(
+                env,
+                &tx,
+                fee_bump,
+                options.padding.inclusion_fee,
+                &options.network_passphrase,
+            )
+        })
+        .transpose()?;
+    let diffs: Vec<_> = simulation
+        .modified_entries
+        .iter()
+        .map(|diff| (diff.state_before.clone(), diff.state_after.clone()))
+        .collect();
+    let preview = preview::position_preview(
+        env,
+        &call,
+        &diffs,
+        &simulation.contract_events,
+        &options.output,
+    );
+
+    // build again the simulated transaction after we updated the resources
+    let envelope = TransactionEnvelope::Tx(TransactionV1Envelope {
+        tx: tx.clone(),
+        signatures: std::vec![].try_into().unwrap(),
+    });
+    let tx_xdr = to_base64(&envelope);
+    let sep7_uri = options
+        .sep7
+        .as_ref()
+        .map(|sep7| transaction::sep7_uri(&tx_xdr, sep7, &options.network_passphrase))
+        .transpose()?;
+    let (signature_payload, hash) = transaction::signature_payload(
+        env,
+        &options.network_passphrase,
+        TransactionSignaturePayloadTaggedTransaction::Tx(tx.clone()),
+    );
+    Ok(Response {
+        tx: tx_xdr,
+        hash: to_hex(&hash),
+        signature_payload: to_base64(&signature_payload),
+        return_value: to_base64(&return_value),
+        min_resource_fee,
+        resource_fee: v1ext.resource_fee,
+        fee: tx.fee,
+        instructions: resources.instructions,
+        read_bytes: resources.read_bytes,
+        write_bytes: resources.write_bytes,
+        footprint: Footprint {
+            read_only: resources
+                .footprint
+                .read_only
+                .iter()
+                .map(to_base64)
+                .collect(),
+            read_write: resources
+                .footprint
+                .read_write
+                .iter()
+                .map(to_base64)
+                .collect(),
+        },
+        auth: simulation.auth.iter().map(to_base64).collect(),
+        events: simulation.contract_events.iter().map(to_base64).collect(),
+        latest_ledger: env.soroban().ledger().sequence(),
+        fee_bump,
+        preview,
+        pool_address: call
+            .deploy_salt
+            .map(|salt| deploy::predicted_address(env, salt)),
+        sep7_uri,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shape(val: &ScVal) -> ArgShape {
+        match val {
+            ScVal::Address(_) => ArgShape::Address,
+            ScVal::I128(_) => ArgShape::I128,
+            ScVal::I32(_) => ArgShape::I32,
+            ScVal::Symbol(_) => ArgShape::Symbol,
+            ScVal::Bytes(_) => ArgShape::Hash,
+            _ => panic!("unexpected argument {:?}", val),
+        }
+    }
+
+    #[test]
+    fn requests_match_pool_functions() {
+        let contract = "CAYRXB3BP4VPUTH6NFAI543HOVOD6AVN4KKASSIR3GALG23EV5DQT5G5".to_string();
+        let from = "GAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAWHF".to_string();
+
+        let requests = [
+            (
+                SimulationRequest::Deposit(SimulateDeposit {
+                    contract: contract.clone(),
+                    from: from.clone(),
+                    amount: Amount::Units(10),
+                }),
+                &DEPOSIT,
+            ),
+            (
+                SimulationRequest::UpdateFeeRewards(SimulateUpdateFeeRewards {
+                    contract: contract.clone(),
+                    from: from.clone(),
+                    period: 1,
+                }),
+                &UPDATE_FEE_REWARDS,
+            ),
+            (
+                SimulationRequest::WithdrawMatured(SimulateWithdrawMatured {
+                    contract: contract.clone(),
+                    from: from.clone(),
+                    period: 1,
+                }),
+                &WITHDRAW_MATURED,
+            ),
+            (
+                SimulationRequest::Withdraw(SimulateWithdraw {
+                    contract: contract.clone(),
+                    from: from.clone(),
+                    period: 1,
+                }),
+                &WITHDRAW,
+            ),
+            (
+                SimulationRequest::Subscribe(SimulateSubscribe {
+                    contract: contract.clone(),
+                    from: from.clone(),
+                    amount: Amount::Units(10),
+                }),
+                &SUBSCRIBE,
+            ),
+            (
+                SimulationRequest::ClaimReward(SimulateClaimReward {
+                    contract: contract.clone(),
+                    from: from.clone(),
+                }),
+                &CLAIM_REWARD,
+            ),
+            (
+                SimulationRequest::SetMultiplier(SimulateSetMultiplier {
+                    contract: contract.clone(),
+                    from: from.clone(),
+                    multiplier: 2,
+                }),
+                &SET_MULTIPLIER,
+            ),
+            (
+                SimulationRequest::SetVolatility(SimulateSetVolatility {
+                    contract: contract.clone(),
+                    from: from.clone(),
+                    volatility: 10,
+                }),
+                &SET_VOLATILITY,
+            ),
+            (
+                SimulationRequest::SetOracle(SimulateSetOracle {
+                    contract: contract.clone(),
+                    from: from.clone(),
+                    oracle: contract.clone(),
+                    oracle_asset: "XLM".to_string(),
+                }),
+                &SET_ORACLE,
+            ),
+            (
+                SimulationRequest::SetAdmin(SimulateSetAdmin {
+                    contract: contract.clone(),
+                    from: from.clone(),
+                    admin: from.clone(),
+                }),
+                &SET_ADMIN,
+            ),
+            (
+                SimulationRequest::Upgrade(SimulateUpgrade {
+                    contract: contract.clone(),
+                    from: from.clone(),
+                    wasm_hash: "00".repeat(32),
+                }),
+                &UPGRADE,
+            ),
+        ];
+
+        for (request, function) in requests {
+            let call = request.call().unwrap();
+            assert_eq!(call.function, function.name);
+            assert_eq!(call.admin, function.admin);
+            let shapes: Vec<ArgShape> = call.args.iter().map(shape).collect();
+            assert_eq!(shapes, function.args, "arguments of {}", function.name);
+        }
+
+        assert!(matches!(
+            ContractCall::new(&contract, &from, &DEPOSIT, vec![Arg::I128(1)]),
+            Err(SimulationError::InvalidArgument { .. })
+        ));
+        assert!(matches!(
+            ContractCall::new(
+                &contract,
+                &from,
+                &WITHDRAW,
+                vec![Arg::Address(from.clone()), Arg::I128(1)]
+            ),
+            Err(SimulationError::InvalidArgument { .. })
+        ));
+    }
+
+    #[test]
+    fn json_args() {
+        let args: Vec<JsonArg> = serde_json::from_str(
+            r#"[
+                {"type": "i128", "value": "-170141183460469231731687303715884105728"},
+                {"type": "bytes", "value": "0x00ff"},
+                {"type": "map", "value": [
+                    {"key": {"type": "symbol", "value": "b"}, "value": {"type": "bool", "value": true}},
+                    {"key": {"type": "symbol", "value": "a"}, "value": {"type": "vec", "value": [{"type": "u32", "value": 1}]}}
+                ]}
+            ]"#,
+        )
+        .unwrap();
+        let vals: Vec<ScVal> = args.iter().map(|arg| arg.to_scval().unwrap()).collect();
+
+        assert_eq!(vals[0], ScVal::from(i128::MIN));
+        assert_eq!(
+            vals[1],
+            ScVal::Bytes(ScBytes(vec![0, 255].try_into().unwrap()))
+        );
+        let ScVal::Map(Some(map)) = &vals[2] else {
+            panic!()
+        };
+        assert_eq!(map[0].key, ScVal::Symbol(ScSymbol("a".try_into().unwrap())));
+
+        assert!(JsonArg::Bytes("abc".into()).to_scval().is_err());
+        assert!(JsonArg::Address("GABC".into()).to_scval().is_err());
+    }
+
+    #[test]
+    fn body_with_options() {
+        let body: SimulationBody = serde_json::from_str(
+            r#"{"ClaimReward": {"contract": "C", "from": "G"}, "padding": {"resource_fee_pct": 20}}"#,
+        )
+        .unwrap();
+        assert!(matches!(body.request, SimulationRequest::ClaimReward(_)));
+
+        let policy = PaddingPolicy::from_request(body.options.padding.as_ref());
+        assert_eq!(policy.resource_fee_pct, 20);
+        assert_eq!(policy.read_bytes, DEFAULT_PADDING.read_bytes);
+
+        let body: SimulationBody =
+            serde_json::from_str(r#"{"ClaimReward": {"contract": "C", "from": "G"}}"#).unwrap();
+        assert!(body.options.padding.is_none());
+
+        let body: SimulationBody = serde_json::from_str(
+            r#"{"Deposit": {"contract": "C", "from": "G", "amount": 100000000000000000000}}"#,
+        )
+        .unwrap();
+        let SimulationRequest::Deposit(deposit) = body.request else {
+            panic!()
+        };
+        assert!(matches!(
+            deposit.amount.to_stroops(None),
+            Err(SimulationError::InvalidAmount { .. })
+        ));
+
+        let batch: BatchSimulationBody = serde_json::from_str(
+            r#"{"requests": [{"ClaimReward": {"contract": "C", "from": "G"}}], "source": "G"}"#,
+        )
+        .unwrap();
+        assert_eq!(batch.requests.len(), 1);
+        assert_eq!(batch.options.source.as_deref(), Some("G"));
+        assert!(batch.options.fee_bump.is_none());
+    }
+
+    #[test]
+    fn padding() {
+        assert_eq!(pad_u32(1000, 10, 5), 1105);
+        assert_eq!(pad_u32(u32::MAX, 10, 5), u32::MAX);
+        assert_eq!(pad_u32(1000, 0, 0), 1000);
+    }
+
+    #[test]
+    fn errors() {
+        let error = ContractCall::custom("CAYRX", "G", "deposit", &[])
+            .err()
+            .unwrap();
+        assert!(matches!(error, SimulationError::InvalidAddress { .. }));
+
+        let request = SimulationRequest::WithdrawAllMatured(SimulateWithdrawAllMatured {
+            contract: "CAYRXB3BP4VPUTH6NFAI543HOVOD6AVN4KKASSIR3GALG23EV5DQT5G5".to_string(),
+            from: "GAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAWHF".to_string(),
+        });
+        assert!(matches!(
+            request.call(),
+            Err(SimulationError::InvalidArgument { .. })
+        ));
+
+        let response = SimulationResponse::from(Err(SimulationError::SimulationFailed {
+            contract_error_code: contract_error_code(&ScVal::Error(ScError::Contract(3))),
+            error: String::new(),
+            diagnostic_events: vec![],
+        }));
+        let json = serde_json::to_value(response).unwrap();
+        assert_eq!(json["error"]["type"], "SimulationFailed");
+        assert_eq!(json["error"]["contract_error_code"], 3);
+    }
+
+    #[test]
+    fn sources() {
+        let account = "GAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAWHF";
+        let muxed = stellar_strkey::ed25519::MuxedAccount {
+            ed25519: [1; 32],
+            id: 7,
+        }
+        .to_string();
+
+        let source = TxSource::for_address(&muxed).unwrap();
+        assert_eq!(source.account, [1; 32]);
+        assert!(matches!(source.muxed, MuxedAccount::MuxedEd25519(ref m) if m.id == 7));
+        assert_eq!(
+            address_to_scval(&muxed).unwrap(),
+            address_to_scval(&stellar_strkey::ed25519::PublicKey([1; 32]).to_string()).unwrap()
+        );
+
+        let wallet = "CAYRXB3BP4VPUTH6NFAI543HOVOD6AVN4KKASSIR3GALG23EV5DQT5G5";
+        assert!(matches!(
+            TxSource::resolve(wallet, None),
+            Err(SimulationError::InvalidArgument { .. })
+        ));
+        assert_eq!(
+            TxSource::resolve(wallet, Some(account)).unwrap().account,
+            [0; 32]
+        );
+        assert_eq!(TxSource::for_address(account).unwrap().account, [0; 32]);
+
+        let relayed = TxSource::resolve(&muxed, Some(account)).unwrap();
+        assert_eq!(relayed.account, [0; 32]);
+        assert!(TxSource::resolve(
+            account,
+            Some("CAYRXB3BP4VPUTH6NFAI543HOVOD6AVN4KKASSIR3GALG23EV5DQT5G5")
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn batch_sequences() {
+        let source = |key: u8| {
+            TxSource::for_address(&stellar_strkey::ed25519::PublicKey([key; 32]).to_string())
+                .unwrap()
+        };
+        let failed = || SimulationError::InvalidArgument {
+            reason: String::new(),
+        };
+
+        // the invalid item and the failing simulation don't use up a sequence.
+        let items = vec![
+            Ok(("deposit", source(1))),
+            Err(failed()),
+            Ok(("fails", source(1))),
+            Ok(("withdraw", source(1))),
+        ];
+        assert_eq!(batch_account(&items).unwrap(), Some([1; 32]));
+        let sequences: Vec<_> = chain_sequences(items, 10, |call, _, sequence| {
+            if call == "fails" {
+                return Err(failed());
+            }
+            Ok(sequence)
+        })
+        .into_iter()
+        .map(Result::ok)
+        .collect();
+        assert_eq!(sequences, [Some(11), None, None, Some(12)]);
+
+        let mixed = vec![Ok(((), source(1))), Err(failed()), Ok(((), source(2)))];
+        assert!(matches!(
+            batch_account(&mixed),
+            Err(SimulationError::InvalidArgument { .. })
+        ));
+        assert_eq!(batch_account::<()>(&[Err(failed())]).unwrap(), None);
+    }
+}
@@ -0,0 +1,382 @@
+use crate::{
+    config::DEFAULT_TX_VALIDITY_SECONDS,
+    encoding::{decode_hex, percent_encode, to_hex},
+    simulation::TxSource,
+    types::*,
+};
+use zephyr_sdk::{
+    prelude::*,
+    soroban_sdk::{
+        xdr::{
+            Duration, FeeBumpTransaction, FeeBumpTransactionEnvelope, FeeBumpTransactionExt,
+            FeeBumpTransactionInnerTx, Hash, LedgerBounds, Memo, Preconditions, PreconditionsV2,
+            TimeBounds, TimePoint, Transaction, TransactionExt, TransactionSignaturePayload,
+            TransactionSignaturePayloadTaggedTransaction, TransactionV1Envelope, VecM,
+        },
+        Bytes,
+    },
+    EnvClient,
+};
+
+pub(crate) fn sha256(env: &EnvClient, data: &[u8]) -> [u8; 32] {
+    env.soroban()
+        .crypto()
+        .sha256(&Bytes::from_slice(env.soroban(), data))
+        .to_array()
+}
+
+/// Identifier of the network with the given passphrase.
+pub(crate) fn network_id(env: &EnvClient, passphrase: &str) -> Hash {
+    Hash(sha256(env, passphrase.as_bytes()))
+}
+
+/// Payload signed over by the signers of `transaction` on the given network
+/// (the network id, the envelope type and the transaction), along with its
+/// hash, which is what they actually sign.
+pub(crate) fn signature_payload(
+    env: &EnvClient,
+    passphrase: &str,
+    transaction: TransactionSignaturePayloadTaggedTransaction,
+) -> (TransactionSignaturePayload, [u8; 32]) {
+    let payload = TransactionSignaturePayload {
+        network_id: network_id(env, passphrase),
+        tagged_transaction: transaction,
+    };
+    let hash = sha256(env, &payload.to_xdr(Limits::none()).unwrap());
+
+    (payload, hash)
+}
+
+fn invalid(reason: String) -> SimulationError {
+    SimulationError::InvalidArgument { reason }
+}
+
+/// Converts the requested memo into the transaction memo.
+pub(crate) fn memo(memo: Option<&TxMemo>) -> Result<Memo, SimulationError> {
+    let memo = match memo {
+        None => Memo::None,
+        Some(TxMemo::Text(text)) => Memo::Text(
+            text.as_bytes()
+                .to_vec()
+                .try_into()
+                .map_err(|_| invalid(format!("memo text is longer than 28 bytes: {}", text)))?,
+        ),
+        Some(TxMemo::Id(id)) => Memo::Id(*id),
+        Some(TxMemo::Hash(hash)) => Memo::Hash(Hash(
+            decode_hex(hash)
+                .ok()
+                .and_then(|hash| hash.try_into().ok())
+                .ok_or_else(|| invalid(format!("invalid memo hash {}", hash)))?,
+        )),
+    };
+
+    Ok(memo)
+}
+
+/// Builds the preconditions of a transaction from the requested bounds,
+/// expiring it `DEFAULT_TX_VALIDITY_SECONDS` after `now` unless the request
+/// sets a `max_time`.
+pub(crate) fn preconditions(
+    bounds: Option<&Bounds>,
+    now: u64,
+) -> Result<Preconditions, SimulationError> {
+    let default = Bounds::default();
+    let bounds = bounds.unwrap_or(&default);
+
+    let min_time = bounds.min_time.unwrap_or(0);
+    let max_time = bounds.max_time.unwrap_or(now + DEFAULT_TX_VALIDITY_SECONDS);
+    if max_time != 0 && min_time > max_time {
+        return Err(invalid(format!(
+            "min_time {} is after max_time {}",
+            min_time, max_time
+        )));
+    }
+    let time_bounds = TimeBounds {
+        min_time: TimePoint(min_time),
+        max_time: TimePoint(max_time),
+    };
+
+    if bounds.min_ledger.is_none() && bounds.max_ledger.is_none() {
+        return Ok(Preconditions::Time(time_bounds));
+    }
+    let min_ledger = bounds.min_ledger.unwrap_or(0);
+    let max_ledger = bounds.max_ledger.unwrap_or(0);
+    if max_ledger != 0 && min_ledger > max_ledger {
+        return Err(invalid(format!(
+            "min_ledger {} is after max_ledger {}",
+            min_ledger, max_ledger
+        )));
+    }
+
+    Ok(Preconditions::V2(PreconditionsV2 {
+        time_bounds: Some(time_bounds),
+        ledger_bounds: Some(LedgerBounds {
+            min_ledger,
+            max_ledger,
+        }),
+        min_seq_num: None,
+        min_seq_age: Duration(0),
+        min_seq_ledger_gap: 0,
+        extra_signers: VecM::default(),
+    }))
+}
+
+/// Longest `msg` allowed in a SEP-7 URI.
+const SEP7_MAX_MSG_LENGTH: usize = 300;
+
+/// Builds the SEP-7 URI asking a wallet to sign the base64 transaction
+/// envelope `xdr` on the network with the given passphrase.
+pub(crate) fn sep7_uri(
+    xdr: &str,
+    request: &Sep7,
+    passphrase: &str,
+) -> Result<String, SimulationError> {
+    if request.origin_domain.is_some() {
+        return Err(invalid(
+            "SEP-7 origin_domain requires signing the URI, which isn't supported".to_string(),
+        ));
+    }
+
+    let mut uri = format!("web+stellar:tx?xdr={}", percent_encode(xdr));
+
+    if let Some(callback) = &request.callback {
+        uri.push_str(&format!(
+            "&callback={}",
+            percent_encode(&format!("url:{}", callback))
+        ));
+    }
+    if let Some(msg) = &request.msg {
+        if msg.chars().count() > SEP7_MAX_MSG_LENGTH {
+            return Err(invalid(format!(
+                "SEP-7 message is longer than {} characters",
+                SEP7_MAX_MSG_LENGTH
+            )));
+        }
+        uri.push_str(&format!("&msg={}", percent_encode(msg)));
+    }
+    uri.push_str(&format!(
+        "&network_passphrase={}",
+        percent_encode(passphrase)
+    ));
+
+    Ok(uri)
+}
+
+/// Lowest fee a fee bump of `tx` can bid: the inner resource fee plus the
+/// inclusion fee for each operation and for the fee bump itself.
+pub(crate) fn minimum_fee_bump_fee(tx: &Transaction, inclusion_fee: u32) -> i64 {
+    let resource_fee = match &tx.ext {
+        TransactionExt::V1(v1ext) => v1ext.resource_fee,
+        TransactionExt::V0 => 0,
+    };
+
+    resource_fee + inclusion_fee as i64 * (tx.operations.len() as i64 + 1)
+}
+
+/// Wraps `tx` in a fee bump paid by `request.sponsor`, returning the
+/// unsigned fee bump envelope and the hashes both signers need to sign.
+pub(crate) fn fee_bump(
+    env: &EnvClient,
+    tx: &Transaction,
+    request: &FeeBump,
+    inclusion_fee: u32,
+    passphrase: &str,
+) -> Result<FeeBumpResponse, SimulationError> {
+    let sponsor = TxSource::account(&request.sponsor)?;
+    let minimum = minimum_fee_bump_fee(tx, inclusion_fee);
+    let fee = request.fee.unwrap_or(minimum);
+    if fee < minimum {
+        return Err(SimulationError::InvalidArgument {
+            reason: format!("fee bump fee {} is below the minimum {}", fee, minimum),
+        });
+    }
+
+    let fee_bump = FeeBumpTransaction {
+        fee_source: sponsor.muxed,
+        fee,
+        inner_tx: FeeBumpTransactionInnerTx::Tx(TransactionV1Envelope {
+            tx: tx.clone(),
+            signatures: std::vec![].try_into().unwrap(),
+        }),
+        ext: FeeBumpTransactionExt::V0,
+    };
+
+    let (_, inner_hash) = signature_payload(
+        env,
+        passphrase,
+        TransactionSignaturePayloadTaggedTransaction::Tx(tx.clone()),
+    );
+    let (_, fee_bump_hash) = signature_payload(
+        env,
+        passphrase,
+        TransactionSignaturePayloadTaggedTransaction::TxFeeBump(fee_bump.clone()),
+    );
+    let envelope = FeeBumpTransactionEnvelope {
+        tx: fee_bump,
+        signatures: std::vec![].try_into().unwrap(),
+    };
+
+    Ok(FeeBumpResponse {
+        tx: envelope.to_xdr_base64(Limits::none()).unwrap(),
+        fee,
+        inner_hash: to_hex(&inner_hash),
+        hash: to_hex(&fee_bump_hash),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NETWORK_PASSPHRASE;
+    use zephyr_sdk::soroban_sdk::xdr::{
+        LedgerFootprint, MuxedAccount, Operation, OperationBody, SequenceNumber, SorobanResources,
+        SorobanTransactionData, Uint256,
+    };
+
+    #[test]
+    fn minimum_fee() {
+        let tx = Transaction {
+            source_account: MuxedAccount::Ed25519(Uint256([0; 32])),
+            fee: 1100,
+            seq_num: SequenceNumber(1),
+            cond: Preconditions::None,
+            memo: Memo::None,
+            operations: std::vec![Operation {
+                source_account: None,
+                body: OperationBody::Inflation,
+            }]
+            .try_into()
+            .unwrap(),
+            ext: TransactionExt::V1(SorobanTransactionData {
+                ext: zephyr_sdk::soroban_sdk::xdr::ExtensionPoint::V0,
+                resources: SorobanResources {
+                    footprint: LedgerFootprint {
+                        read_only: Default::default(),
+                        read_write: Default::default(),
+                    },
+                    instructions: 0,
+                    read_bytes: 0,
+                    write_bytes: 0,
+                },
+                resource_fee: 1000,
+            }),
+        };
+
+        assert_eq!(minimum_fee_bump_fee(&tx, 100), 1200);
+    }
+
+    #[test]
+    fn hashes() {
+        let env = EnvClient::empty();
+        let tx = Transaction {
+            source_account: MuxedAccount::Ed25519(Uint256([0; 32])),
+            fee: 100,
+            seq_num: SequenceNumber(1),
+            cond: Preconditions::None,
+            memo: Memo::None,
+            operations: std::vec![Operation {
+                source_account: None,
+                body: OperationBody::Inflation,
+            }]
+            .try_into()
+            .unwrap(),
+            ext: TransactionExt::V0,
+        };
+
+        let (payload, hash) = signature_payload(
+            &env,
+            "Test SDF Network ; September 2015",
+            TransactionSignaturePayloadTaggedTransaction::Tx(tx),
+        );
+        assert_eq!(
+            to_hex(&payload.network_id.0),
+            "cee0302d59844d32bdca915c8203dd44b33fbb7edc19051ea37abedf28ecd472"
+        );
+        assert_eq!(
+            to_hex(&payload.to_xdr(Limits::none()).unwrap()),
+            "cee0302d59844d32bdca915c8203dd44b33fbb7edc19051ea37abedf28ecd472\
+             00000002\
+             00000000\
+             0000000000000000000000000000000000000000000000000000000000000000\
+             00000064\
+             0000000000000001\
+             00000000\
+             00000000\
+             00000001000000000000000900000000"
+        );
+        assert_eq!(
+            to_hex(&hash),
+            "619e35866ce68699e34c5b3e33ca4b5955e6c9d582d20cca79c29f052d0ca4cc"
+        );
+    }
+
+    #[test]
+    fn memos() {
+        assert_eq!(memo(None).unwrap(), Memo::None);
+        assert_eq!(memo(Some(&TxMemo::Id(42))).unwrap(), Memo::Id(42));
+        assert!(matches!(
+            memo(Some(&TxMemo::Text("order 42".into()))).unwrap(),
+            Memo::Text(_)
+        ));
+        assert!(memo(Some(&TxMemo::Text("x".repeat(29)))).is_err());
+        assert_eq!(
+            memo(Some(&TxMemo::Hash("ab".repeat(32)))).unwrap(),
+            Memo::Hash(Hash([0xab; 32]))
+        );
+        assert!(memo(Some(&TxMemo::Hash("ab".into()))).is_err());
+    }
+
+    #[test]
+    fn bounds() {
+        let Preconditions::Time(time) = preconditions(None, 1000).unwrap() else {
+            panic!()
+        };
+        assert_eq!(time.max_time.0, 1000 + DEFAULT_TX_VALIDITY_SECONDS);
+
+        let unbounded = Bounds {
+            max_time: Some(0),
+            min_ledger: Some(10),
+            ..Default::default()
+        };
+        let Preconditions::V2(v2) = preconditions(Some(&unbounded), 1000).unwrap() else {
+            panic!()
+        };
+        assert_eq!(v2.time_bounds.unwrap().max_time.0, 0);
+        assert_eq!(v2.ledger_bounds.unwrap().min_ledger, 10);
+
+        let inverted = Bounds {
+            min_time: Some(2000),
+            max_time: Some(1500),
+            ..Default::default()
+        };
+        assert!(preconditions(Some(&inverted), 1000).is_err());
+    }
+
+    #[test]
+    fn sep7() {
+        let request = Sep7 {
+            callback: Some("https://example.com/tx".into()),
+            msg: Some("Deposit 10 USDC".into()),
+            origin_domain: None,
+        };
+        assert_eq!(
+            sep7_uri("AAAA+/8=", &request, "Test SDF Network ; September 2015").unwrap(),
+            "web+stellar:tx?xdr=AAAA%2B%2F8%3D\
+             &callback=url%3Ahttps%3A%2F%2Fexample.com%2Ftx\
+             &msg=Deposit%2010%20USDC\
+             &network_passphrase=Test%20SDF%20Network%20%3B%20September%202015"
+        );
+
+        let signed = Sep7 {
+            origin_domain: Some("example.com".into()),
+            ..Default::default()
+        };
+        assert!(sep7_uri("AAAA", &signed, NETWORK_PASSPHRASE).is_err());
+
+        let long = Sep7 {
+            msg: Some("x".repeat(301)),
+            ..Default::default()
+        };
+        assert!(sep7_uri("AAAA", &long, NETWORK_PASSPHRASE).is_err());
+    }
+}
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use zephyr_sdk::{
     prelude::*,
-    soroban_sdk::{self, contracttype, xdr::ScVal, Address},
+    soroban_sdk::{self, contracttype, Address, Symbol},
     DatabaseDerive, EnvClient,
 };
 
@@ -11,9 +11,216 @@ pub struct PoolsTable {
     pub address: String,
 }
 
+#[derive(DatabaseDerive, Clone, Serialize)]
+#[with_name("poolstate")]
+pub struct PoolStateTable {
+    pub address: String,
+    pub token_id: String,
+    pub genesis: i32,
+    pub periods: i32,
+    pub oracle: String,
+    pub symbol: String,
+    pub external: bool,
+    pub asset: String,
+    pub vol: i128,
+    pub admin: String,
+    pub mult: i32,
+    pub liquidity: i128,
+    pub supply: i128,
+    pub refund: i128,
+    pub period: i32,
+}
+
+#[derive(DatabaseDerive, Clone, Serialize)]
+#[with_name("oracles")]
+pub struct OraclesTable {
+    pub pool: String,
+    pub price: i128,
+    pub timestamp: u64,
+    pub ledger: u32,
+    pub deviation: i128,
+    pub alerted: bool,
+}
+
+#[derive(DatabaseDerive, Clone, Serialize)]
+#[with_name("alerts")]
+pub struct AlertsTable {
+    pub pool: String,
+    pub kind: String,
+    pub ledger: u32,
+    pub detail: String,
+}
+
+/// Ledger keys touched by a transaction, as base64 XDR.
+#[derive(Serialize)]
+pub struct Footprint {
+    pub read_only: Vec<String>,
+    pub read_write: Vec<String>,
+}
+
+/// Simulated transaction along with what the simulation reported. XDR
+/// values are base64-encoded.
 #[derive(Serialize)]
 pub struct Response {
     pub tx: String,
+    /// Hex-encoded hash of the transaction, which its signers sign.
+    pub hash: String,
+    /// `TransactionSignaturePayload` hashed into `hash`.
+    pub signature_payload: String,
+    pub return_value: String,
+    pub min_resource_fee: i64,
+    pub resource_fee: i64,
+    pub fee: u32,
+    pub instructions: u32,
+    pub read_bytes: u32,
+    pub write_bytes: u32,
+    pub footprint: Footprint,
+    pub auth: Vec<String>,
+    pub events: Vec<String>,
+    pub latest_ledger: u32,
+    pub fee_bump: Option<FeeBumpResponse>,
+    pub preview: Option<PositionPreview>,
+    /// Address of the pool created by a `DeployPool` transaction.
+    pub pool_address: Option<String>,
+    /// SEP-7 URI asking a wallet to sign `tx`.
+    pub sep7_uri: Option<String>,
+}
+
+/// Change of the caller's position in the pool if the simulated transaction
+/// is submitted. Balances and totals are the values after the call, for the
+/// period the call acts on.
+#[derive(Serialize)]
+pub struct PositionPreview {
+    pub period: Option<i32>,
+    pub shares_minted: JsonInt,
+    pub balance: Option<JsonInt>,
+    pub principal: Option<JsonInt>,
+    pub tot_supply: Option<JsonInt>,
+    pub tot_liquidity: Option<JsonInt>,
+    pub tokens_returned: JsonInt,
+    pub fees_realized: JsonInt,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub formatted: Option<FormattedPreview>,
+}
+
+/// Token amounts of a [`PositionPreview`] formatted with the token decimals.
+#[derive(Serialize)]
+pub struct FormattedPreview {
+    pub principal: Option<String>,
+    pub tot_liquidity: Option<String>,
+    pub tokens_returned: String,
+    pub fees_realized: String,
+}
+
+/// Unsigned fee bump wrapping the simulated transaction. The inner
+/// transaction is signed by its source over `inner_hash`, then the sponsor
+/// signs the fee bump over `hash`. Hashes are hex-encoded.
+#[derive(Serialize)]
+pub struct FeeBumpResponse {
+    pub tx: String,
+    pub fee: i64,
+    pub inner_hash: String,
+    pub hash: String,
+}
+
+/// Reasons a simulation can fail, returned to the caller as
+/// `{"error": {"type": "AccountNotFound", ...}}`.
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "type")]
+pub enum SimulationError {
+    InvalidAddress {
+        address: String,
+    },
+    InvalidArgument {
+        reason: String,
+    },
+    AccountNotFound {
+        account: String,
+    },
+    SimulationFailed {
+        contract_error_code: Option<u32>,
+        error: String,
+        diagnostic_events: Vec<String>,
+    },
+    UnexpectedEnvelope,
+    HostError {
+        reason: String,
+    },
+    NotAdmin {
+        from: String,
+        admin: String,
+    },
+    InsufficientBalance {
+        account: String,
+        token: String,
+        balance: JsonInt,
+        required: JsonInt,
+    },
+    /// `change_trust` is the unsigned transaction adding the trustline, when
+    /// the account can source it.
+    MissingTrustline {
+        account: String,
+        asset: String,
+        change_trust: Option<String>,
+    },
+    InvalidAmount {
+        amount: String,
+        reason: String,
+    },
+    InvalidPeriod {
+        period: i32,
+        reason: PeriodError,
+        valid_periods: Vec<i32>,
+    },
+}
+
+/// Why a withdrawal can't be made for the requested period.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PeriodError {
+    NotMatured,
+    NoBalance,
+    AlreadyWithdrawn,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum SimulationResponse {
+    Success(Box<Response>),
+    Failure { error: SimulationError },
+}
+
+impl From<Result<Response, SimulationError>> for SimulationResponse {
+    fn from(result: Result<Response, SimulationError>) -> Self {
+        match result {
+            Ok(response) => Self::Success(Box::new(response)),
+            Err(error) => Self::Failure { error },
+        }
+    }
+}
+
+/// Response of `simulate_batch`: one result per request, in order, or a
+/// single error when the batch as a whole is invalid.
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum BatchSimulationResponse {
+    Success(Vec<SimulationResponse>),
+    Failure { error: SimulationError },
+}
+
+impl From<Result<Vec<Result<Response, SimulationError>>, SimulationError>>
+    for BatchSimulationResponse
+{
+    fn from(result: Result<Vec<Result<Response, SimulationError>>, SimulationError>) -> Self {
+        match result {
+            Ok(responses) => Self::Success(
+                responses
+                    .into_iter()
+                    .map(SimulationResponse::from)
+                    .collect(),
+            ),
+            Err(error) => Self::Failure { error },
+        }
+    }
 }
 
 #[derive(Serialize)]
@@ -26,15 +233,62 @@ pub struct PoolData {
     pub symbol: String,
     pub external: bool,
     pub oracle_asset: String,
-    pub volatility: i128,
+    pub volatility: JsonInt,
     pub admin: String,
     pub multiplier: i32,
-    pub tot_liquidity: i128,
-    pub tot_supply: i128,
-    pub refund_global: i128,
+    pub tot_liquidity: JsonInt,
+    pub tot_supply: JsonInt,
+    pub refund_global: JsonInt,
+    pub oracle_stale: bool,
+    pub oracle_price_jump: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub formatted: Option<FormattedPoolData>,
+}
+
+/// Token amounts of a [`PoolData`] formatted with the token decimals.
+#[derive(Serialize)]
+pub struct FormattedPoolData {
+    pub tot_liquidity: String,
+    pub tot_supply: String,
+    pub refund_global: String,
+}
+
+/// How i128 values are encoded in responses. JSON numbers lose precision
+/// above 2^53 in most clients.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum IntEncoding {
+    Number,
+    String,
+}
+
+/// An i128 encoded as requested by the caller.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum JsonInt {
+    Number(i128),
+    String(String),
+}
+
+/// Rendering options of a response. `i128` defaults to numbers, or to
+/// strings in the `_v2` functions, and `formatted` adds token amounts
+/// formatted with the token decimals.
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+pub struct Output {
+    #[serde(default)]
+    pub i128: Option<IntEncoding>,
+    #[serde(default)]
+    pub formatted: bool,
+}
+
+/// Body of a `get_pools_v2` request.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct PoolsRequest {
+    #[serde(default)]
+    pub output: Output,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 #[contracttype]
 pub enum InstanceDataKey {
     TokenId,
@@ -52,8 +306,8 @@ pub enum InstanceDataKey {
 #[derive(Clone)]
 #[contracttype]
 pub struct BalanceObject {
-    address: Address,
-    period: i32,
+    pub address: Address,
+    pub period: i32,
 }
 
 #[derive(Clone)]
@@ -70,11 +324,25 @@ pub enum PersistentDataKey {
     RefundGlobal(i32),
 }
 
+#[derive(Clone)]
+#[contracttype]
+pub enum Asset {
+    Stellar(Address),
+    Other(Symbol),
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct PriceData {
+    pub price: i128,
+    pub timestamp: u64,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct SimulateDeposit {
     pub contract: String,
     pub from: String,
-    pub amount: i64,
+    pub amount: Amount,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -102,7 +370,20 @@ pub struct SimulateWithdraw {
 pub struct SimulateSubscribe {
     pub contract: String,
     pub from: String,
-    pub amount: i128,
+    pub amount: Amount,
+}
+
+/// Token amount, either in stroops (`"1000"` or `1000`) or as a decimal
+/// string (`"12.5"`) scaled by the token decimals. JSON numbers above 2^64
+/// can't be read exactly and are rejected as `InvalidAmount`, so larger
+/// amounts that used to be sent as numbers must now be sent as strings.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum Amount {
+    Units(i128),
+    Text(String),
+    /// A JSON number that isn't an exact integer, kept to be rejected.
+    Inexact(f64),
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -111,6 +392,100 @@ pub struct SimulateClaimReward {
     pub from: String,
 }
 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SimulateSetMultiplier {
+    pub contract: String,
+    pub from: String,
+    pub multiplier: i32,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SimulateSetVolatility {
+    pub contract: String,
+    pub from: String,
+    pub volatility: i128,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SimulateSetOracle {
+    pub contract: String,
+    pub from: String,
+    pub oracle: String,
+    pub oracle_asset: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SimulateSetAdmin {
+    pub contract: String,
+    pub from: String,
+    pub admin: String,
+}
+
+/// Withdrawal of every matured position of `from`, simulated through
+/// `simulate_batch` as an `update_fee_rewards` and `withdraw_matured`
+/// transaction per period.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SimulateWithdrawAllMatured {
+    pub contract: String,
+    pub from: String,
+}
+
+/// Deployment of a new pool through the factory. `salt` is the hex-encoded
+/// 32-byte salt that determines the pool address.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SimulateDeployPool {
+    pub from: String,
+    pub salt: String,
+    pub token_id: String,
+    pub periods: i32,
+    pub oracle: String,
+    pub symbol: String,
+    pub external: bool,
+    pub oracle_asset: String,
+    pub volatility: i128,
+    pub admin: String,
+    pub multiplier: i32,
+}
+
+/// Upgrade of the pool to an already uploaded WASM, given by its hex hash.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SimulateUpgrade {
+    pub contract: String,
+    pub from: String,
+    pub wasm_hash: String,
+}
+
+/// A contract argument along with its Soroban type, e.g.
+/// `{"type": "u32", "value": 1}` or `{"type": "i128", "value": "-100"}`.
+/// `i128` values are strings, `bytes` are hex-encoded.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", content = "value", rename_all = "lowercase")]
+pub enum JsonArg {
+    Address(String),
+    I128(String),
+    U32(u32),
+    I32(i32),
+    Bool(bool),
+    Symbol(String),
+    Bytes(String),
+    Vec(Vec<JsonArg>),
+    Map(Vec<JsonMapEntry>),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct JsonMapEntry {
+    pub key: JsonArg,
+    pub value: JsonArg,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SimulateCustom {
+    pub contract: String,
+    pub from: String,
+    pub function: String,
+    pub args: Vec<JsonArg>,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub enum SimulationRequest {
     Deposit(SimulateDeposit),
@@ -119,6 +494,122 @@ pub enum SimulationRequest {
     Withdraw(SimulateWithdraw),
     Subscribe(SimulateSubscribe),
     ClaimReward(SimulateClaimReward),
+    Custom(SimulateCustom),
+    SetMultiplier(SimulateSetMultiplier),
+    SetVolatility(SimulateSetVolatility),
+    SetOracle(SimulateSetOracle),
+    SetAdmin(SimulateSetAdmin),
+    Upgrade(SimulateUpgrade),
+    DeployPool(SimulateDeployPool),
+    WithdrawAllMatured(SimulateWithdrawAllMatured),
+}
+
+/// Margins added on top of the simulated resources. Unset fields fall back
+/// to the server defaults.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Padding {
+    pub instructions: Option<u32>,
+    pub read_bytes: Option<u32>,
+    pub write_bytes: Option<u32>,
+    pub resource_fee: Option<i64>,
+    pub instructions_pct: Option<u32>,
+    pub read_bytes_pct: Option<u32>,
+    pub write_bytes_pct: Option<u32>,
+    pub resource_fee_pct: Option<u32>,
+    pub inclusion_fee: Option<u32>,
+}
+
+/// Account sponsoring the fees of the simulated transaction through a fee
+/// bump. `fee` defaults to the minimum the fee bump can bid.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FeeBump {
+    pub sponsor: String,
+    #[serde(default)]
+    pub fee: Option<i64>,
+}
+
+/// Memo attached to the simulated transaction, e.g.
+/// `{"type": "text", "value": "order 42"}` or `{"type": "id", "value": 42}`.
+/// Hashes are hex-encoded.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "type", content = "value", rename_all = "lowercase")]
+pub enum TxMemo {
+    Text(String),
+    Id(u64),
+    Hash(String),
+}
+
+/// Validity window of the simulated transaction. Times are unix timestamps
+/// in seconds and `0` means unbounded. `max_time` defaults to a short expiry
+/// after the current ledger close time.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Bounds {
+    #[serde(default)]
+    pub min_time: Option<u64>,
+    #[serde(default)]
+    pub max_time: Option<u64>,
+    #[serde(default)]
+    pub min_ledger: Option<u32>,
+    #[serde(default)]
+    pub max_ledger: Option<u32>,
+}
+
+/// Parameters of the SEP-7 `web+stellar:tx` URI returned with the simulated
+/// transaction. `callback` is the URL the wallet posts the signed
+/// transaction to. `origin_domain` is rejected, as SEP-7 requires it to come
+/// with a signature of the URI by that domain, which the indexer can't make.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Sep7 {
+    #[serde(default)]
+    pub callback: Option<String>,
+    #[serde(default)]
+    pub msg: Option<String>,
+    #[serde(default)]
+    pub origin_domain: Option<String>,
+}
+
+/// Options of the simulated transactions, shared by `simulate` and
+/// `simulate_batch` requests.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct TxOptions {
+    #[serde(default)]
+    pub padding: Option<Padding>,
+    /// Account sourcing and paying for the transaction, when it differs from
+    /// the acting address (e.g. a relayer). Required when `from` is a contract
+    /// wallet.
+    #[serde(default)]
+    pub source: Option<String>,
+    #[serde(default)]
+    pub fee_bump: Option<FeeBump>,
+    #[serde(default)]
+    pub output: Output,
+    #[serde(default)]
+    pub memo: Option<TxMemo>,
+    #[serde(default)]
+    pub bounds: Option<Bounds>,
+    #[serde(default)]
+    pub sep7: Option<Sep7>,
+    #[serde(default)]
+    pub network_passphrase: Option<String>,
+}
+
+/// Body of a `simulate` request: the action plus request-wide options,
+/// e.g. `{"Deposit": {...}, "padding": {"resource_fee_pct": 20}}`.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SimulationBody {
+    #[serde(flatten)]
+    pub request: SimulationRequest,
+    #[serde(flatten)]
+    pub options: TxOptions,
+}
+
+/// Body of a `simulate_batch` request: actions sharing the same transaction
+/// source, plus the options applied to all of them.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BatchSimulationBody {
+    pub requests: Vec<SimulationRequest>,
+    #[serde(flatten)]
+    pub options: TxOptions,
 }
 
 /*